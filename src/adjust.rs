@@ -0,0 +1,164 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+  Left,
+  Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+  Char,
+  Word,
+  // Up to the next whitespace
+  Blank,
+}
+
+// Keys available in adjust mode: key, grows, side, unit
+const ADJUSTMENTS: [(char, bool, Side, Unit); 12] = [
+  ('h', true, Side::Left, Unit::Char),
+  ('l', true, Side::Right, Unit::Char),
+  ('H', false, Side::Left, Unit::Char),
+  ('L', false, Side::Right, Unit::Char),
+  ('b', true, Side::Left, Unit::Word),
+  ('w', true, Side::Right, Unit::Word),
+  ('B', false, Side::Left, Unit::Word),
+  ('W', false, Side::Right, Unit::Word),
+  ('[', true, Side::Left, Unit::Blank),
+  (']', true, Side::Right, Unit::Blank),
+  ('{', false, Side::Left, Unit::Blank),
+  ('}', false, Side::Right, Unit::Blank),
+];
+
+// Selected chars of a line, from `start` up to `end` (not included)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+}
+
+fn is_separator(c: char, unit: Unit) -> bool {
+  match unit {
+    Unit::Char => false,
+    Unit::Word => !(c.is_alphanumeric() || c == '_'),
+    Unit::Blank => c.is_whitespace(),
+  }
+}
+
+// Steps needed to cross the separators and the next unit of chars
+fn steps<I>(mut chars: I, unit: Unit) -> usize
+where
+  I: Iterator<Item = char> + Clone,
+{
+  if unit == Unit::Char {
+    return chars.next().map(|_| 1).unwrap_or(0);
+  }
+
+  let skipped = chars.clone().take_while(|&c| is_separator(c, unit)).count();
+  let content = chars
+    .skip(skipped)
+    .take_while(|&c| !is_separator(c, unit))
+    .count();
+
+  skipped + content
+}
+
+// Steps needed to drop the next unit of chars and the separators after it
+fn drop_steps<I>(chars: I, unit: Unit) -> usize
+where
+  I: Iterator<Item = char> + Clone,
+{
+  let count = steps(chars.clone(), unit);
+  let separators = chars
+    .skip(count)
+    .take_while(|&c| is_separator(c, unit))
+    .count();
+
+  count + separators
+}
+
+impl Span {
+  pub fn adjust(&self, chars: &[char], grow: bool, side: Side, unit: Unit) -> Span {
+    match (grow, side) {
+      (true, Side::Left) => Span {
+        start: self.start - steps(chars[..self.start].iter().rev().cloned(), unit),
+        end: self.end,
+      },
+      (true, Side::Right) => Span {
+        start: self.start,
+        end: self.end + steps(chars[self.end..].iter().cloned(), unit),
+      },
+      (false, Side::Left) => {
+        let inner = chars[self.start..self.end - 1].iter().cloned();
+
+        Span {
+          start: self.start + drop_steps(inner, unit),
+          end: self.end,
+        }
+      }
+      (false, Side::Right) => {
+        let inner = chars[self.start + 1..self.end].iter().rev().cloned();
+
+        Span {
+          start: self.start,
+          end: self.end - drop_steps(inner, unit),
+        }
+      }
+    }
+  }
+}
+
+pub fn get_adjustment(key: char) -> Option<(bool, Side, Unit)> {
+  ADJUSTMENTS
+    .iter()
+    .find(|adjustment| adjustment.0 == key)
+    .map(|&(_, grow, side, unit)| (grow, side, unit))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn adjust(text: &str, span: Span, key: char) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let (grow, side, unit) = get_adjustment(key).unwrap();
+    let span = span.adjust(&chars, grow, side, unit);
+
+    chars[span.start..span.end].iter().collect()
+  }
+
+  #[test]
+  fn adjust_chars() {
+    let span = Span { start: 6, end: 14 };
+    assert_eq!(adjust("error /tmp/foo:12 here", span, 'l'), "/tmp/foo:");
+    assert_eq!(adjust("error /tmp/foo:12 here", span, 'h'), " /tmp/foo");
+    assert_eq!(adjust("error /tmp/foo:12 here", span, 'H'), "tmp/foo");
+    assert_eq!(adjust("error /tmp/foo:12 here", span, 'L'), "/tmp/fo");
+  }
+
+  #[test]
+  fn adjust_words() {
+    let span = Span { start: 6, end: 14 };
+    assert_eq!(adjust("error /tmp/foo:12 here", span, 'w'), "/tmp/foo:12");
+    assert_eq!(adjust("error /tmp/foo:12 here", span, 'b'), "error /tmp/foo");
+    assert_eq!(adjust("error /tmp/foo:12 here", span, 'B'), "foo");
+    assert_eq!(adjust("error /tmp/foo:12 here", span, 'W'), "/tmp");
+  }
+
+  #[test]
+  fn adjust_blanks() {
+    let span = Span { start: 11, end: 14 };
+    assert_eq!(adjust("error /tmp/foo:12 here", span, ']'), "foo:12");
+    assert_eq!(adjust("error /tmp/foo:12 here", span, '['), "/tmp/foo");
+
+    let span = Span { start: 0, end: 17 };
+    assert_eq!(adjust("error /tmp/foo:12 here", span, '}'), "error");
+    assert_eq!(adjust("error /tmp/foo:12 here", span, '{'), "/tmp/foo:12");
+  }
+
+  #[test]
+  fn adjust_keeps_one_char() {
+    let span = Span { start: 6, end: 7 };
+    assert_eq!(adjust("error /tmp/foo:12 here", span, 'H'), "/");
+    assert_eq!(adjust("error /tmp/foo:12 here", span, 'W'), "/");
+    assert_eq!(adjust("error /tmp/foo:12 here", Span { start: 0, end: 5 }, 'h'), "error");
+  }
+}
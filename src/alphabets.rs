@@ -1,6 +1,7 @@
 use std::collections::HashMap;
+use std::fs;
 
-const ALPHABETS: [(&'static str, &'static str); 22] = [
+const ALPHABETS: [(&'static str, &'static str); 32] = [
   ("numeric", "1234567890"),
   ("abcd", "abcd"),
   ("qwerty", "asdfqwerzxcvjklmiuopghtybn"),
@@ -23,15 +24,80 @@ const ALPHABETS: [(&'static str, &'static str); 22] = [
   ("colemak-homerow", "arstneiodh"),
   ("colemak-left-hand", "arstqwfpzxcv"),
   ("colemak-right-hand", "neioluymjhk"),
+  ("russian-jcuken", "фывайцукячсмолдьшгщзпренит"),
+  ("russian-jcuken-homerow", "фываолдпр"),
+  ("russian-jcuken-left-hand", "фывайцукясчм"),
+  ("russian-jcuken-right-hand", "олдгшщзьнрт"),
+  ("bepo", "auiectsrnmbépovdljzwyxkqghf"),
+  ("bepo-homerow", "auiectsrnm"),
+  ("workman", "ashtneoigyqdrwbjfupzxmcvkl"),
+  ("workman-homerow", "ashtneoigy"),
+  ("neo", "uiaeosnrtdyxvlcwkhgfqpzbmj"),
+  ("neo-homerow", "uiaeosnrtd"),
 ];
 
-pub struct Alphabet<'a> {
-  letters: &'a str,
+pub struct Alphabet {
+  letters: String,
 }
 
-impl<'a> Alphabet<'a> {
-  fn new(letters: &'a str) -> Alphabet {
-    Alphabet { letters: letters }
+impl Alphabet {
+  pub fn new(letters: &str) -> Alphabet {
+    Alphabet {
+      letters: letters.to_string(),
+    }
+  }
+
+  pub fn letters(&self) -> &str {
+    self.letters.as_str()
+  }
+
+  // Rejects alphabets that would build ambiguous hints or hints that can't be
+  // typed because their letters are already bound to another action
+  pub fn validate(&self, reserved: &[char], allow_upcase: bool) -> Result<(), String> {
+    if self.letters.is_empty() {
+      return Err("Empty alphabet".to_string());
+    }
+
+    let mut seen: Vec<char> = Vec::new();
+
+    for letter in self.letters.chars() {
+      if seen.contains(&letter) {
+        return Err(format!("Duplicated letter in alphabet: {}", letter));
+      }
+
+      if letter.is_control() || letter.is_whitespace() {
+        return Err(format!("Control key in alphabet: {:?}", letter));
+      }
+
+      if letter.is_uppercase() && !allow_upcase {
+        return Err(format!("Upcase letter in alphabet: {}", letter));
+      }
+
+      if reserved.contains(&letter) {
+        return Err(format!("Reserved key in alphabet: {}", letter));
+      }
+
+      seen.push(letter);
+    }
+
+    Ok(())
+  }
+
+  pub fn without(&self, excluded: &[char]) -> Alphabet {
+    Alphabet {
+      letters: self.letters.chars().filter(|c| !excluded.contains(c)).collect(),
+    }
+  }
+
+  // Biggest amount of hints that `hints` is able to build with these letters
+  pub fn capacity(&self) -> usize {
+    let count = self.letters.chars().count();
+
+    if count > 1 {
+      count * count
+    } else {
+      count
+    }
   }
 
   pub fn hints(&self, matches: usize) -> Vec<String> {
@@ -44,7 +110,7 @@ impl<'a> Alphabet<'a> {
       if expansion.len() + expanded.len() >= matches {
         break;
       }
-      if expansion.len() == 0 {
+      if expansion.is_empty() {
         break;
       }
 
@@ -61,21 +127,69 @@ impl<'a> Alphabet<'a> {
     expansion = expansion
       .iter()
       .take(matches - expanded.len())
-      .map(|s| s.clone())
+      .cloned()
       .collect();
     expansion.append(&mut expanded);
     expansion
   }
 }
 
-pub fn get_alphabet(alphabet_name: &str) -> Alphabet {
+pub fn expand_home(path: &str) -> String {
+  match (path.starts_with("~/"), std::env::var("HOME")) {
+    (true, Ok(home)) => format!("{}{}", home, &path[1..]),
+    _ => path.to_string(),
+  }
+}
+
+// Alphabet files have one `name = letters` definition per line. Empty lines
+// and lines starting with `#` are ignored.
+fn parse_alphabets(content: &str) -> Result<HashMap<String, String>, String> {
+  let mut alphabets = HashMap::new();
+
+  for line in content.lines() {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with("#") {
+      continue;
+    }
+
+    let mut parts = line.splitn(2, "=");
+    let name = parts.next().unwrap().trim();
+    let letters = parts.next().map(|letters| letters.trim());
+
+    match letters {
+      Some(letters) if !name.is_empty() => {
+        alphabets.insert(name.to_string(), letters.to_string());
+      }
+      _ => return Err(format!("Invalid alphabet definition: {}", line)),
+    }
+  }
+
+  Ok(alphabets)
+}
+
+pub fn get_alphabet(alphabet_name: &str, alphabets_file: Option<&str>) -> Result<Alphabet, String> {
+  if let Some(letters) = alphabet_name.strip_prefix("custom:") {
+    return Ok(Alphabet::new(letters));
+  }
+
+  if let Some(path) = alphabets_file {
+    let path = expand_home(path);
+    let content =
+      fs::read_to_string(&path).map_err(|e| format!("Unable to read alphabets file {}: {}", path, e))?;
+    let alphabets = parse_alphabets(&content)?;
+
+    if let Some(letters) = alphabets.get(alphabet_name) {
+      return Ok(Alphabet::new(letters));
+    }
+  }
+
   let alphabets: HashMap<&str, &str> = ALPHABETS.iter().cloned().collect();
 
   alphabets
     .get(alphabet_name)
-    .expect(format!("Unknown alphabet: {}", alphabet_name).as_str()); // FIXME
-
-  Alphabet::new(alphabets[alphabet_name])
+    .map(|letters| Alphabet::new(letters))
+    .ok_or_else(|| format!("Unknown alphabet: {}", alphabet_name))
 }
 
 #[cfg(test)]
@@ -109,4 +223,50 @@ mod tests {
     let hints = alphabet.hints(8);
     assert_eq!(hints, ["aa", "ab", "ba", "bb"]);
   }
+
+  #[test]
+  fn unicode_matches() {
+    let alphabet = get_alphabet("russian-jcuken-homerow", None).unwrap();
+    let hints = alphabet.hints(10);
+    assert_eq!(hints, ["ф", "ы", "в", "а", "о", "л", "д", "п", "рф", "ры"]);
+    assert_eq!(alphabet.capacity(), 81);
+  }
+
+  #[test]
+  fn custom_alphabet() {
+    let alphabet = get_alphabet("custom:hjkl", None).unwrap();
+    assert_eq!(alphabet.letters(), "hjkl");
+  }
+
+  #[test]
+  fn unknown_alphabet() {
+    assert!(get_alphabet("klingon", None).is_err());
+  }
+
+  #[test]
+  fn alphabets_file() {
+    let alphabets = parse_alphabets("# Mine\n\nhome = hjkl\nleft=asdf\n").unwrap();
+    assert_eq!(alphabets.get("home").unwrap(), "hjkl");
+    assert_eq!(alphabets.get("left").unwrap(), "asdf");
+    assert!(parse_alphabets("hjkl").is_err());
+  }
+
+  #[test]
+  fn validate_alphabet() {
+    assert!(Alphabet::new("asdf").validate(&[], false).is_ok());
+    assert!(Alphabet::new("").validate(&[], false).is_err());
+    assert!(Alphabet::new("asda").validate(&[], false).is_err());
+    assert!(Alphabet::new("as f").validate(&[], false).is_err());
+    assert!(Alphabet::new("asDf").validate(&[], false).is_err());
+    assert!(Alphabet::new("asDf").validate(&[], true).is_ok());
+    assert!(Alphabet::new("asdf").validate(&['k', 'd'], false).is_err());
+  }
+
+  #[test]
+  fn without_letters() {
+    let alphabet = Alphabet::new("abcd").without(&['b', 'd']);
+    let hints = alphabet.hints(3);
+    assert_eq!(hints, ["a", "ca", "cc"]);
+    assert_eq!(alphabet.capacity(), 4);
+  }
 }
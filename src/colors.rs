@@ -23,6 +23,14 @@ pub fn get_color(color_name: &str) -> Color {
   available_colors[&color_name]
 }
 
+pub fn get_color_name(color: Color) -> &'static str {
+  COLORS
+    .iter()
+    .find(|&&(_, known)| known == color)
+    .map(|&(name, _)| name)
+    .unwrap_or("default")
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -31,4 +39,10 @@ mod tests {
   fn match_color() {
     assert_eq!(get_color("green"), Color::Green);
   }
+
+  #[test]
+  fn color_name() {
+    assert_eq!(get_color_name(Color::Green), "green");
+    assert_eq!(get_color_name(Color::Byte(42)), "default");
+  }
 }
@@ -0,0 +1,332 @@
+use std::io;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+// Splits a command line in words like a shell does with quotes and
+// backslashes, without any expansion
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+  let unterminated = || format!("Unterminated quote in command: {}", line);
+  let mut words = Vec::new();
+  let mut current: Option<String> = None;
+  let mut chars = line.chars();
+
+  while let Some(ch) = chars.next() {
+    match ch {
+      '\'' => {
+        let word = current.get_or_insert_with(String::new);
+
+        loop {
+          match chars.next() {
+            Some('\'') => break,
+            Some(ch) => word.push(ch),
+            None => return Err(unterminated()),
+          }
+        }
+      }
+      '"' => {
+        let word = current.get_or_insert_with(String::new);
+
+        loop {
+          match chars.next() {
+            Some('"') => break,
+            Some('\\') => match chars.next() {
+              Some(ch) if "\"\\$`".contains(ch) => word.push(ch),
+              Some(ch) => {
+                word.push('\\');
+                word.push(ch);
+              }
+              None => return Err(unterminated()),
+            },
+            Some(ch) => word.push(ch),
+            None => return Err(unterminated()),
+          }
+        }
+      }
+      '\\' => match chars.next() {
+        Some(ch) => current.get_or_insert_with(String::new).push(ch),
+        None => return Err(format!("Trailing backslash in command: {}", line)),
+      },
+      ch if ch.is_whitespace() => {
+        if let Some(word) = current.take() {
+          words.push(word);
+        }
+      }
+      ch => current.get_or_insert_with(String::new).push(ch),
+    }
+  }
+
+  if let Some(word) = current {
+    words.push(word);
+  }
+
+  Ok(words)
+}
+
+fn url_encode(text: &str) -> String {
+  text
+    .bytes()
+    .map(|byte| match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+      _ => format!("%{:02X}", byte),
+    })
+    .collect()
+}
+
+fn shell_quote(text: &str) -> String {
+  format!("'{}'", text.replace("'", "'\\''"))
+}
+
+// What was picked, available as `{name}` placeholders in the templates and as
+// `THUMBS_NAME` environment variables. `{}` is the picked text.
+pub struct Context {
+  values: Vec<(&'static str, String)>,
+}
+
+impl Context {
+  pub fn new(
+    text: &str,
+    pattern: Option<&str>,
+    position: Option<(usize, usize)>,
+    pane: Option<&str>,
+    pane_current_path: Option<&str>,
+  ) -> Context {
+    let (line, col) = match position {
+      Some((line, col)) => ((line + 1).to_string(), (col + 1).to_string()),
+      None => (String::new(), String::new()),
+    };
+
+    Context {
+      values: vec![
+        ("text", text.to_string()),
+        ("pattern", pattern.unwrap_or("").to_string()),
+        ("line", line),
+        ("col", col),
+        ("pane", pane.unwrap_or("").to_string()),
+        ("pane_current_path", pane_current_path.unwrap_or("").to_string()),
+        ("url_encoded", url_encode(text)),
+        ("shell_quoted", shell_quote(text)),
+      ],
+    }
+  }
+
+  fn value(&self, name: &str) -> Option<&str> {
+    let name = if name.is_empty() { "text" } else { name };
+
+    self
+      .values
+      .iter()
+      .find(|(known, _)| *known == name)
+      .map(|(_, value)| value.as_str())
+  }
+
+  // Placeholders are replaced in a single pass, so values containing braces
+  // are never replaced again
+  fn replace(&self, word: &str) -> String {
+    let mut result = String::new();
+    let mut rest = word;
+
+    while let Some(start) = rest.find('{') {
+      let value = rest[start..]
+        .find('}')
+        .and_then(|end| self.value(&rest[start + 1..start + end]).map(|value| (end, value)));
+
+      match value {
+        Some((end, value)) => {
+          result.push_str(&rest[..start]);
+          result.push_str(value);
+          rest = &rest[start + end + 1..];
+        }
+        None => {
+          result.push_str(&rest[..start + 1]);
+          rest = &rest[start + 1..];
+        }
+      }
+    }
+
+    result.push_str(rest);
+    result
+  }
+}
+
+// Command run with the picked text. The template is split in words before
+// replacing the placeholders, so every value ends up in a single argument
+// whatever it contains. A leading `|` writes the text to the standard input of
+// the command too.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+  words: Vec<String>,
+  stdin: bool,
+}
+
+impl Template {
+  pub fn parse(template: &str) -> Result<Template, String> {
+    let (stdin, template) = match template.trim_start().strip_prefix('|') {
+      Some(rest) => (true, rest),
+      None => (false, template),
+    };
+
+    let words = split_words(template)?;
+
+    if words.is_empty() {
+      return Err("Empty command".to_string());
+    }
+
+    Ok(Template { words, stdin })
+  }
+
+  pub fn arguments(&self, context: &Context) -> Vec<String> {
+    self.words.iter().map(|word| context.replace(word)).collect()
+  }
+
+  pub fn run(&self, context: &Context) -> io::Result<Output> {
+    let arguments = self.arguments(context);
+    let text = context.value("text").unwrap_or("");
+    let mut command = Command::new(&arguments[0]);
+
+    command.args(&arguments[1..]);
+
+    for &(name, ref value) in context.values.iter() {
+      command.env(format!("THUMBS_{}", name.to_uppercase()), value);
+    }
+
+    if !self.stdin {
+      return command.output();
+    }
+
+    let mut child = command
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()?;
+
+    // The text is written from another thread while the output is read, as the
+    // command could wait for its output to be read before reading more. Commands
+    // that don't read all of it are fine.
+    let writer = child.stdin.take().map(|mut input| {
+      let text = text.to_string();
+
+      thread::spawn(move || match input.write_all(text.as_bytes()) {
+        Err(ref error) if error.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+      })
+    });
+
+    let output = child.wait_with_output()?;
+
+    if let Some(Ok(result)) = writer.map(|writer| writer.join()) {
+      result?;
+    }
+
+    Ok(output)
+  }
+
+  // Runs the command, failing with its standard error when it can't be
+  // spawned or exits with an error
+  pub fn execute(&self, context: &Context) -> Result<(), String> {
+    let program = &self.words[0];
+    let output = self
+      .run(context)
+      .map_err(|error| format!("Couldn't run {}: {}", program, error))?;
+
+    if output.status.success() {
+      return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = stderr.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    match output.status.code() {
+      Some(code) if stderr.is_empty() => Err(format!("{} failed with exit code {}", program, code)),
+      Some(code) => Err(format!("{} failed with exit code {}: {}", program, code, stderr)),
+      None => Err(format!("{} was killed by a signal", program)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn split_command_words() {
+    assert_eq!(split_words("tmux set-buffer {}"), Ok(vec!["tmux".to_string(), "set-buffer".to_string(), "{}".to_string()]));
+    assert_eq!(split_words("  a   b "), Ok(vec!["a".to_string(), "b".to_string()]));
+    assert_eq!(split_words("echo 'a b' \"c \\\"d\\\" \\n\""), Ok(vec!["echo".to_string(), "a b".to_string(), "c \"d\" \\n".to_string()]));
+    assert_eq!(split_words("a\\ b c''d \"\""), Ok(vec!["a b".to_string(), "cd".to_string(), "".to_string()]));
+    assert!(split_words("echo 'a").is_err());
+    assert!(split_words("echo \"a").is_err());
+    assert!(split_words("echo a\\").is_err());
+  }
+
+  #[test]
+  fn template_arguments() {
+    let template = Template::parse("tmux set-buffer -- '{}'").unwrap();
+    let context = Context::new("a b; rm -rf ~ 'c'", None, None, None, None);
+
+    assert_eq!(template.arguments(&context), ["tmux", "set-buffer", "--", "a b; rm -rf ~ 'c'"]);
+
+    let template = Template::parse("xdg-open https://tracker.example.com/{}").unwrap();
+    let context = Context::new("T-12 x", None, None, None, None);
+
+    assert_eq!(template.arguments(&context), ["xdg-open", "https://tracker.example.com/T-12 x"]);
+    assert!(Template::parse("  ").is_err());
+  }
+
+  #[test]
+  fn template_failures() {
+    let context = Context::new("foo", None, None, None, None);
+
+    assert_eq!(Template::parse("true {}").unwrap().execute(&context), Ok(()));
+    assert_eq!(
+      Template::parse("sh -c 'echo \"no  such\nthing\" >&2; exit 3'").unwrap().execute(&context),
+      Err("sh failed with exit code 3: no such thing".to_string())
+    );
+    assert_eq!(
+      Template::parse("false").unwrap().execute(&context),
+      Err("false failed with exit code 1".to_string())
+    );
+    assert!(Template::parse("thumbs-missing-command")
+      .unwrap()
+      .execute(&context)
+      .unwrap_err()
+      .starts_with("Couldn't run thumbs-missing-command"));
+  }
+
+  #[test]
+  fn template_stdin() {
+    let template = Template::parse(" | xclip -selection clipboard").unwrap();
+
+    assert!(template.stdin);
+    assert_eq!(template.words, ["xclip", "-selection", "clipboard"]);
+    assert!(!Template::parse("echo |").unwrap().stdin);
+    assert!(Template::parse("|").is_err());
+
+    // Longer than the pipe buffers, so it's only done if written and read at
+    // the same time
+    let context = Context::new(&"x".repeat(100_000), None, None, None, None);
+    let output = Template::parse("|cat").unwrap().run(&context).unwrap();
+
+    assert_eq!(output.stdout.len(), 100_000);
+    assert_eq!(Template::parse("| true").unwrap().execute(&context), Ok(()));
+  }
+
+  #[test]
+  fn template_placeholders() {
+    let template = Template::parse("open {pattern}:{line}:{col} {pane} {pane_current_path} {unknown} {{}").unwrap();
+    let context = Context::new("{pattern}", Some("path"), Some((2, 5)), Some("%1"), Some("/tmp"));
+
+    assert_eq!(
+      template.arguments(&context),
+      ["open", "path:3:6", "%1", "/tmp", "{unknown}", "{{pattern}"]
+    );
+
+    let template = Template::parse("echo {} {url_encoded} {shell_quoted}").unwrap();
+    let context = Context::new("a b/{}'c'", None, None, None, None);
+
+    assert_eq!(
+      template.arguments(&context),
+      ["echo", "a b/{}'c'", "a%20b%2F%7B%7D%27c%27", "'a b/{}'\\''c'\\'''"]
+    );
+  }
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+// What is hinted on screen
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Granularity {
+  #[default]
+  Pattern,
+  Word,
+  Line,
+  Paragraph,
+}
+
+const GRANULARITIES: [(&str, Granularity); 4] = [
+  ("pattern", Granularity::Pattern),
+  ("word", Granularity::Word),
+  ("line", Granularity::Line),
+  ("paragraph", Granularity::Paragraph),
+];
+
+impl Granularity {
+  // Lines and paragraphs get their hints in the left gutter
+  pub fn uses_gutter(&self) -> bool {
+    *self == Granularity::Line || *self == Granularity::Paragraph
+  }
+}
+
+pub fn get_granularity(granularity_name: &str) -> Result<Granularity, String> {
+  let granularities: HashMap<&str, Granularity> = GRANULARITIES.iter().cloned().collect();
+
+  granularities
+    .get(granularity_name)
+    .cloned()
+    .ok_or_else(|| format!("Unknown granularity: {}", granularity_name))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn match_granularity() {
+    assert_eq!(get_granularity("line"), Ok(Granularity::Line));
+    assert!(get_granularity("sentence").is_err());
+  }
+}
@@ -0,0 +1,314 @@
+use super::alphabets::expand_home;
+use super::operators::Operator;
+use rustbox::Key;
+use std::fs;
+
+// termbox doesn't know Shift-Tab, so the view reports it with this key
+pub const BACK_TAB: Key = Key::Unknown(0x5a);
+
+// What a key does in the overlay, besides typing hints
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+  Cancel,
+  Confirm,
+  Next,
+  Prev,
+  Up,
+  Down,
+  Left,
+  Right,
+  First,
+  Last,
+  PageUp,
+  PageDown,
+  Multi,
+  Bulk,
+  Range,
+  Block,
+  Filter,
+  Search,
+  Help,
+  Operate(Operator),
+}
+
+// Operators have no default keys, as they would clash with most alphabets
+const ACTIONS: [(&str, Action, &str); 24] = [
+  ("cancel", Action::Cancel, "esc"),
+  ("confirm", Action::Confirm, "enter"),
+  ("next", Action::Next, "tab"),
+  ("prev", Action::Prev, "backtab"),
+  ("up", Action::Up, "up"),
+  ("down", Action::Down, "down"),
+  ("left", Action::Left, "left"),
+  ("right", Action::Right, "right"),
+  ("first", Action::First, "home"),
+  ("last", Action::Last, "end"),
+  ("page-up", Action::PageUp, "pageup"),
+  ("page-down", Action::PageDown, "pagedown"),
+  ("multi", Action::Multi, "space"),
+  ("bulk", Action::Bulk, "*"),
+  ("range", Action::Range, "-"),
+  ("block", Action::Block, "|"),
+  ("filter", Action::Filter, "/"),
+  ("search", Action::Search, ":"),
+  ("help", Action::Help, "?"),
+  ("copy", Action::Operate(Operator::Copy), ""),
+  ("paste", Action::Operate(Operator::Paste), ""),
+  ("open", Action::Operate(Operator::Open), ""),
+  ("edit", Action::Operate(Operator::Edit), ""),
+  ("send", Action::Operate(Operator::Send), ""),
+];
+
+const KEYS: [(&str, Key); 13] = [
+  ("esc", Key::Esc),
+  ("enter", Key::Enter),
+  ("tab", Key::Tab),
+  ("backtab", BACK_TAB),
+  ("backspace", Key::Backspace),
+  ("up", Key::Up),
+  ("down", Key::Down),
+  ("left", Key::Left),
+  ("right", Key::Right),
+  ("home", Key::Home),
+  ("end", Key::End),
+  ("pageup", Key::PageUp),
+  ("pagedown", Key::PageDown),
+];
+
+// Named keys, `space`, `C-x` for Ctrl combinations or a single character
+pub fn get_key(key_name: &str) -> Result<Key, String> {
+  if let Some(&(_, key)) = KEYS.iter().find(|&&(name, _)| name == key_name) {
+    return Ok(key);
+  }
+
+  let mut chars = key_name.chars();
+
+  match (key_name, chars.next(), chars.next(), chars.next()) {
+    ("space", _, _, _) => Ok(Key::Char(' ')),
+    (_, Some('C'), Some('-'), Some(ch)) if chars.next().is_none() => Ok(Key::Ctrl(ch)),
+    (_, Some(ch), None, _) if !ch.is_control() => Ok(Key::Char(ch)),
+    _ => Err(format!("Unknown key: {}", key_name)),
+  }
+}
+
+fn key_name(key: Key) -> String {
+  if let Some(&(name, _)) = KEYS.iter().find(|&&(_, named)| named == key) {
+    return name.to_string();
+  }
+
+  match key {
+    Key::Char(' ') => "space".to_string(),
+    Key::Char(ch) => ch.to_string(),
+    Key::Ctrl(ch) => format!("C-{}", ch),
+    _ => format!("{:?}", key),
+  }
+}
+
+fn get_action(action_name: &str) -> Result<Action, String> {
+  ACTIONS
+    .iter()
+    .find(|&&(name, _, _)| name == action_name)
+    .map(|&(_, action, _)| action)
+    .ok_or(format!("Unknown action: {}", action_name))
+}
+
+pub struct Keymap {
+  bindings: Vec<(Key, Action)>,
+}
+
+impl Keymap {
+  // Bound actions replace all the default keys of that action
+  pub fn new(bindings: &[(Action, Key)]) -> Result<Keymap, String> {
+    let mut keymap = Keymap {
+      bindings: ACTIONS
+        .iter()
+        .filter(|&&(_, _, key)| !key.is_empty())
+        .filter(|&&(_, action, _)| !bindings.iter().any(|&(bound, _)| bound == action))
+        .map(|&(_, action, key)| (get_key(key).unwrap(), action))
+        .collect(),
+    };
+
+    for &(action, key) in bindings {
+      if let Some(&(_, other)) = keymap.bindings.iter().find(|&&(bound, _)| bound == key) {
+        return Err(format!("Key {:?} is bound to {:?} and {:?}", key, other, action));
+      }
+
+      keymap.bindings.push((key, action));
+    }
+
+    Ok(keymap)
+  }
+
+  pub fn action(&self, key: Key) -> Option<Action> {
+    self
+      .bindings
+      .iter()
+      .find(|&&(bound, _)| bound == key)
+      .map(|&(_, action)| action)
+  }
+
+  // Names of the keys bound to every action, in the order of the defaults
+  pub fn bindings(&self) -> Vec<(&'static str, String)> {
+    ACTIONS
+      .iter()
+      .map(|&(name, action, _)| {
+        let keys = self
+          .bindings
+          .iter()
+          .filter(|&&(_, bound)| bound == action)
+          .map(|&(key, _)| key_name(key))
+          .collect::<Vec<_>>();
+
+        (name, keys.join(" "))
+      })
+      .collect()
+  }
+
+  // Characters that can't be used to type hints
+  pub fn chars(&self) -> Vec<char> {
+    self
+      .bindings
+      .iter()
+      .filter_map(|&(key, _)| match key {
+        Key::Char(ch) => Some(ch),
+        _ => None,
+      })
+      .collect()
+  }
+
+  pub fn validate(&self, reserved: &[char]) -> Result<(), String> {
+    match self.chars().iter().find(|ch| reserved.contains(ch)) {
+      Some(ch) => Err(format!("Reserved key in keymap: {}", ch)),
+      None => Ok(()),
+    }
+  }
+
+  // Hints picked with the ctrl upcase modifier need Ctrl with their letters
+  pub fn validate_ctrl(&self, letters: &[char]) -> Result<(), String> {
+    let bound = self.bindings.iter().find_map(|&(key, _)| match key {
+      Key::Ctrl(ch) if letters.contains(&ch) => Some(ch),
+      _ => None,
+    });
+
+    match bound {
+      Some(ch) => Err(format!("Key C-{} in keymap is used to pick hints with ctrl", ch)),
+      None => Ok(()),
+    }
+  }
+}
+
+impl Default for Keymap {
+  fn default() -> Keymap {
+    Keymap::new(&[]).unwrap()
+  }
+}
+
+fn parse_binding(binding: &str) -> Result<Vec<(Action, Key)>, String> {
+  let mut parts = binding.splitn(2, "=");
+  let action = get_action(parts.next().unwrap().trim())?;
+
+  match parts.next().map(|keys| keys.trim()) {
+    Some(keys) if !keys.is_empty() => keys
+      .split_whitespace()
+      .map(|key| get_key(key).map(|key| (action, key)))
+      .collect(),
+    _ => Err(format!("Invalid key binding: {}", binding)),
+  }
+}
+
+// Keymap files have one `action = key [key...]` binding per line. Empty lines
+// and lines starting with `#` are ignored. Bindings are read before the ones
+// passed as arguments.
+pub fn get_keymap(bindings: &[&str], keymap_file: Option<&str>) -> Result<Keymap, String> {
+  let mut parsed = Vec::new();
+
+  if let Some(path) = keymap_file {
+    let path = expand_home(path);
+    let content =
+      fs::read_to_string(&path).map_err(|e| format!("Unable to read keymap file {}: {}", path, e))?;
+
+    for line in content.lines().map(|line| line.trim()) {
+      if !line.is_empty() && !line.starts_with("#") {
+        parsed.extend(parse_binding(line)?);
+      }
+    }
+  }
+
+  for binding in bindings {
+    parsed.extend(parse_binding(binding)?);
+  }
+
+  Keymap::new(&parsed)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn match_keys() {
+    assert_eq!(get_key("esc"), Ok(Key::Esc));
+    assert_eq!(get_key("backtab"), Ok(BACK_TAB));
+    assert_eq!(get_key("space"), Ok(Key::Char(' ')));
+    assert_eq!(get_key("C-g"), Ok(Key::Ctrl('g')));
+    assert_eq!(get_key("q"), Ok(Key::Char('q')));
+    assert_eq!(get_key("C"), Ok(Key::Char('C')));
+    assert!(get_key("escape").is_err());
+    assert!(get_key("C-gg").is_err());
+  }
+
+  #[test]
+  fn default_keymap() {
+    let keymap = get_keymap(&[], None).unwrap();
+
+    assert_eq!(keymap.action(Key::Esc), Some(Action::Cancel));
+    assert_eq!(keymap.action(Key::Char('/')), Some(Action::Filter));
+    assert_eq!(keymap.action(Key::Char('a')), None);
+    assert_eq!(keymap.chars(), [' ', '*', '-', '|', '/', ':', '?']);
+  }
+
+  #[test]
+  fn custom_keymap() {
+    let keymap = get_keymap(&["cancel=C-g", "cancel=esc", "next=C-n", "filter=!"], None).unwrap();
+
+    assert_eq!(keymap.action(Key::Ctrl('g')), Some(Action::Cancel));
+    assert_eq!(keymap.action(Key::Esc), Some(Action::Cancel));
+    assert_eq!(keymap.action(Key::Tab), None);
+    assert_eq!(keymap.action(Key::Char('!')), Some(Action::Filter));
+    assert_eq!(keymap.action(Key::Char('/')), None);
+    assert!(keymap.validate(&['q']).is_ok());
+    assert!(keymap.validate(&['!']).is_err());
+    assert!(keymap.validate(&['/']).is_ok());
+    assert!(keymap.validate_ctrl(&['a', 's', 'd', 'f']).is_ok());
+    assert!(keymap.validate_ctrl(&['f', 'g', 'h']).is_err());
+  }
+
+  #[test]
+  fn operator_keymap() {
+    let keymap = get_keymap(&["open=o", "paste=P"], None).unwrap();
+
+    assert_eq!(keymap.action(Key::Char('o')), Some(Action::Operate(Operator::Open)));
+    assert_eq!(keymap.action(Key::Char('P')), Some(Action::Operate(Operator::Paste)));
+    assert_eq!(keymap.action(Key::Char('y')), None);
+    assert!(keymap.validate(&['o']).is_err());
+  }
+
+  #[test]
+  fn keymap_bindings() {
+    let keymap = get_keymap(&["cancel=C-g", "cancel=esc", "multi=m"], None).unwrap();
+    let bindings = keymap.bindings();
+
+    assert_eq!(bindings[0], ("cancel", "C-g esc".to_string()));
+    assert_eq!(bindings[3], ("prev", "backtab".to_string()));
+    assert_eq!(bindings[12], ("multi", "m".to_string()));
+    assert_eq!(bindings[21], ("open", "".to_string()));
+  }
+
+  #[test]
+  fn conflicting_keymap() {
+    assert!(get_keymap(&["cancel=q", "next=q"], None).is_err());
+    assert!(get_keymap(&["next=esc"], None).is_err());
+    assert!(get_keymap(&["jump=q"], None).is_err());
+    assert!(get_keymap(&["next="], None).is_err());
+  }
+}
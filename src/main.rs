@@ -1,14 +1,23 @@
 extern crate clap;
 extern crate rustbox;
 extern crate base64;
+extern crate unicode_width;
 
+mod adjust;
 mod alphabets;
 mod colors;
+mod commands;
+mod granularities;
+mod keymaps;
+mod modifiers;
+mod normalizers;
+mod operators;
 mod state;
 mod view;
 
 use self::clap::{App, Arg};
 use clap::crate_version;
+use std::collections::HashMap;
 use std::process::Command;
 
 fn exec_command(command: String) -> std::process::Output {
@@ -20,6 +29,55 @@ fn exec_command(command: String) -> std::process::Output {
     .expect("Couldn't run it");
 }
 
+fn parse_prefix(item: &str) -> (&str, char) {
+  let mut parts = item.splitn(2, "=");
+  let pattern = parts.next().unwrap();
+  let mut letters = parts.next().unwrap_or("").chars();
+
+  match (letters.next(), letters.next()) {
+    (Some(letter), None) => (pattern, letter),
+    _ => panic!("Invalid pattern prefix: {}", item),
+  }
+}
+
+fn parse_named(item: &str) -> (&str, &str) {
+  let mut parts = item.splitn(2, "=");
+  let name = parts.next().unwrap();
+
+  match parts.next() {
+    Some(value) if !name.is_empty() => (name, value),
+    _ => panic!("Invalid named value: {}", item),
+  }
+}
+
+// The overlay is already gone when commands fail, so errors are shown in the
+// tmux status line
+fn report(failures: &[String], pane: Option<&str>) {
+  let message = format!("tmux-thumbs: {}", failures.join(" | "));
+
+  eprintln!("{}", message);
+
+  if let Some(pane) = pane {
+    // `#` starts a format in tmux messages
+    let _ = Command::new("tmux")
+      .args(["display-message", "-t", pane, &message.replace("#", "##")])
+      .output();
+  }
+}
+
+fn parse_template(command: &str) -> commands::Template {
+  commands::Template::parse(command).unwrap_or_else(|error| panic!("{}", error))
+}
+
+// Command configured for a pattern, or the global one
+fn command_for<'a>(
+  commands: &'a HashMap<&str, commands::Template>,
+  pattern: Option<&str>,
+  default: &'a commands::Template,
+) -> &'a commands::Template {
+  pattern.and_then(|pattern| commands.get(pattern)).unwrap_or(default)
+}
+
 fn app_args<'a>() -> clap::ArgMatches<'a> {
   return App::new("tmux-thumbs")
     .version(crate_version!())
@@ -31,6 +89,12 @@ fn app_args<'a>() -> clap::ArgMatches<'a> {
         .short("a")
         .default_value("qwerty"),
     )
+    .arg(
+      Arg::with_name("alphabets_file")
+        .help("Load extra named alphabets from this file")
+        .long("alphabets-file")
+        .takes_value(true),
+    )
     .arg(
       Arg::with_name("foreground_color")
         .help("Sets the foregroud color for matches")
@@ -73,6 +137,42 @@ fn app_args<'a>() -> clap::ArgMatches<'a> {
         .long("unique")
         .short("u"),
     )
+    .arg(
+      Arg::with_name("unique_normalize")
+        .help("Normalize duplicated matches with these rules: case, trailing-slash, url, path")
+        .long("unique-normalize")
+        .takes_value(true)
+        .use_delimiter(true)
+        .multiple(true),
+    )
+    .arg(
+      Arg::with_name("multi")
+        .help("Start in multi-select mode")
+        .long("multi")
+        .short("m"),
+    )
+    .arg(
+      Arg::with_name("sticky")
+        .help("Keep the overlay open after picking, until Esc is pressed")
+        .long("sticky")
+        .short("s"),
+    )
+    .arg(
+      Arg::with_name("adjust")
+        .help("Adjust the boundaries of the picked match before confirming it")
+        .long("adjust"),
+    )
+    .arg(
+      Arg::with_name("status")
+        .help("Show the mode, typed hint and selected match in the bottom row")
+        .long("status"),
+    )
+    .arg(
+      Arg::with_name("multi_separator")
+        .help("Join picked matches in multi-select mode with this separator")
+        .long("multi-separator")
+        .default_value(" "),
+    )
     .arg(
       Arg::with_name("osc52")
         .help("Print OSC52 copy escape sequence in addition to running the pick command")
@@ -86,6 +186,13 @@ fn app_args<'a>() -> clap::ArgMatches<'a> {
         .default_value("left")
         .short("p"),
     )
+    .arg(
+      Arg::with_name("granularity")
+        .help("What to hint: pattern, word, line or paragraph")
+        .long("granularity")
+        .short("g")
+        .default_value("pattern"),
+    )
     .arg(
       Arg::with_name("tmux_pane")
         .help("Get this tmux pane as reference pane")
@@ -96,13 +203,37 @@ fn app_args<'a>() -> clap::ArgMatches<'a> {
       Arg::with_name("command")
         .help("Pick command")
         .long("command")
-        .default_value("tmux set-buffer {}"),
+        .default_value("tmux set-buffer -- {}"),
     )
     .arg(
       Arg::with_name("upcase_command")
         .help("Upcase command")
         .long("upcase-command")
-        .default_value("tmux paste-buffer"),
+        .default_value("tmux paste-buffer -t {pane}"),
+    )
+    .arg(
+      Arg::with_name("open_command")
+        .help("Pick command used with the open operator")
+        .long("open-command")
+        .default_value("xdg-open {}"),
+    )
+    .arg(
+      Arg::with_name("edit_command")
+        .help("Pick command used with the edit operator")
+        .long("edit-command")
+        .default_value("tmux new-window vi -- {}"),
+    )
+    .arg(
+      Arg::with_name("send_command")
+        .help("Pick command used with the send operator")
+        .long("send-command")
+        .default_value("tmux send-keys -t {last} -l -- {}"),
+    )
+    .arg(
+      Arg::with_name("upcase_modifier")
+        .help("How to trigger the upcase command: shift, ctrl, enter or a leading key like ;")
+        .long("upcase-modifier")
+        .default_value("shift"),
     )
     .arg(
       Arg::with_name("regexp")
@@ -112,6 +243,34 @@ fn app_args<'a>() -> clap::ArgMatches<'a> {
         .takes_value(true)
         .multiple(true),
     )
+    .arg(
+      Arg::with_name("named_regexp")
+        .help("Use this regexp as extra pattern with a name, like ticket=T-[0-9]+")
+        .long("named-regexp")
+        .takes_value(true)
+        .multiple(true),
+    )
+    .arg(
+      Arg::with_name("pattern_command")
+        .help("Use this command when picking a pattern, like url=xdg-open {}")
+        .long("pattern-command")
+        .takes_value(true)
+        .multiple(true),
+    )
+    .arg(
+      Arg::with_name("pattern_upcase_command")
+        .help("Use this upcase command when picking a pattern, like url=tmux paste-buffer -p -t {pane}")
+        .long("pattern-upcase-command")
+        .takes_value(true)
+        .multiple(true),
+    )
+    .arg(
+      Arg::with_name("pattern_prefix")
+        .help("Reserve a leading hint letter for a pattern, like url=u")
+        .long("pattern-prefix")
+        .takes_value(true)
+        .multiple(true),
+    )
     .arg(
       Arg::with_name("contrast")
         .help("Put square brackets around hint for visibility")
@@ -130,19 +289,84 @@ fn app_args<'a>() -> clap::ArgMatches<'a> {
         .long("copy-mode-down-key")
         .takes_value(true),
     )
+    .arg(
+      Arg::with_name("keymap")
+        .help("Bind a key to an action of the overlay (action=key)")
+        .long("keymap")
+        .takes_value(true)
+        .multiple(true),
+    )
+    .arg(
+      Arg::with_name("keymap_file")
+        .help("Load key bindings from this file")
+        .long("keymap-file")
+        .takes_value(true),
+    )
     .get_matches();
 }
 
 fn main() {
   let args = app_args();
-  let alphabet = args.value_of("alphabet").unwrap();
   let position = args.value_of("position").unwrap();
   let reverse = args.is_present("reverse");
   let unique = args.is_present("unique");
+  let normalizers = if let Some(items) = args.values_of("unique_normalize") {
+    items
+      .map(|item| normalizers::get_normalizer(item).unwrap_or_else(|error| panic!("{}", error)))
+      .collect::<Vec<_>>()
+  } else {
+    [].to_vec()
+  };
+  let multi = args.is_present("multi");
+  let sticky = args.is_present("sticky");
+  let adjust = args.is_present("adjust");
+  let status = args.is_present("status");
+  let granularity = granularities::get_granularity(args.value_of("granularity").unwrap())
+    .unwrap_or_else(|error| panic!("{}", error));
+  let multi_separator = args
+    .value_of("multi_separator")
+    .unwrap()
+    .replace("\\n", "\n")
+    .replace("\\t", "\t");
   let osc52 = args.is_present("osc52");
   let contrast = args.is_present("contrast");
-  let regexp = if let Some(items) = args.values_of("regexp") {
-    items.collect::<Vec<_>>()
+  let mut regexp = if let Some(items) = args.values_of("regexp") {
+    items.map(|item| ("custom", item)).collect::<Vec<_>>()
+  } else {
+    [].to_vec()
+  };
+  if let Some(items) = args.values_of("named_regexp") {
+    regexp.extend(items.map(parse_named));
+  }
+  let named_commands = if let Some(items) = args.values_of("pattern_command") {
+    items.map(parse_named).collect::<Vec<_>>()
+  } else {
+    [].to_vec()
+  };
+  let named_upcase_commands = if let Some(items) = args.values_of("pattern_upcase_command") {
+    items.map(parse_named).collect::<Vec<_>>()
+  } else {
+    [].to_vec()
+  };
+  let pattern_commands = named_commands
+    .iter()
+    .map(|&(pattern, command)| (pattern, parse_template(command)))
+    .collect::<HashMap<_, _>>();
+  let pattern_upcase_commands = named_upcase_commands
+    .iter()
+    .map(|&(pattern, command)| (pattern, parse_template(command)))
+    .collect::<HashMap<_, _>>();
+  let command_patterns = named_commands
+    .iter()
+    .chain(&named_upcase_commands)
+    .map(|&(pattern, _)| pattern)
+    .collect::<Vec<_>>();
+
+  state::validate_patterns(&command_patterns, &regexp, "command")
+    .unwrap_or_else(|error| panic!("{}", error));
+
+  let prefixes = if let Some(items) = args.values_of("pattern_prefix") {
+    items.map(parse_prefix).collect::<Vec<_>>()
   } else {
     [].to_vec()
   };
@@ -157,8 +381,62 @@ fn main() {
   let copy_mode_up_key: Option<char> = args.value_of("copy_mode_up_key").and_then(|s| s.chars().next());
   let copy_mode_down_key: Option<char> = args.value_of("copy_mode_down_key").and_then(|s| s.chars().next());
 
+  let upcase_modifier = modifiers::get_modifier(args.value_of("upcase_modifier").unwrap())
+    .unwrap_or_else(|error| panic!("{}", error));
+
+  let bindings = if let Some(items) = args.values_of("keymap") {
+    items.collect::<Vec<_>>()
+  } else {
+    [].to_vec()
+  };
+
+  let modifier_keys = [copy_mode_up_key, copy_mode_down_key, upcase_modifier.reserved_key()]
+    .iter()
+    .filter_map(|key| *key)
+    .collect::<Vec<char>>();
+
+  let keymap = keymaps::get_keymap(&bindings, args.value_of("keymap_file"))
+    .and_then(|keymap| keymap.validate(&modifier_keys).map(|_| keymap))
+    .unwrap_or_else(|error| panic!("{}", error));
+
+  let mut reserved_keys = keymap.chars();
+  reserved_keys.extend(modifier_keys);
+
+  state::validate_prefixes(&prefixes, &regexp, &reserved_keys, upcase_modifier.allows_upcase())
+    .unwrap_or_else(|error| panic!("{}", error));
+
+  let alphabet = alphabets::get_alphabet(
+    args.value_of("alphabet").unwrap(),
+    args.value_of("alphabets_file"),
+  )
+  .and_then(|alphabet| {
+    alphabet
+      .validate(&reserved_keys, upcase_modifier.allows_upcase())
+      .and_then(|_| upcase_modifier.validate(alphabet.letters()))
+      .map(|_| alphabet)
+  })
+  .unwrap_or_else(|error| panic!("{}", error));
+
+  if upcase_modifier == modifiers::Modifier::Ctrl {
+    let letters = alphabet
+      .letters()
+      .chars()
+      .chain(prefixes.iter().map(|&(_, prefix)| prefix))
+      .collect::<Vec<_>>();
+
+    keymap.validate_ctrl(&letters).unwrap_or_else(|error| panic!("{}", error));
+  }
+
   let command = args.value_of("command").unwrap();
   let upcase_command = args.value_of("upcase_command").unwrap();
+  let open_command = args.value_of("open_command").unwrap();
+  let edit_command = args.value_of("edit_command").unwrap();
+  let send_command = args.value_of("send_command").unwrap();
+  let command_template = parse_template(command);
+  let upcase_template = parse_template(upcase_command);
+  let open_template = parse_template(open_command);
+  let edit_template = parse_template(edit_command);
+  let send_template = parse_template(send_command);
   let tmux_subcommand = if let Some(pane) = args.value_of("tmux_pane") {
     format!(" -t {}", pane)
   } else {
@@ -169,11 +447,71 @@ fn main() {
   let output = String::from_utf8_lossy(&execution.stdout);
   let lines = output.split("\n").collect::<Vec<&str>>();
 
-  let mut state = state::State::new(&lines, alphabet, &regexp);
+  // Commands target the pane where the overlay was open, even in sticky mode
+  // while the overlay is still in its place
+  let pane = args
+    .value_of("tmux_pane")
+    .map(|pane| pane.to_string())
+    .or_else(|| std::env::var("TMUX_PANE").ok());
+
+  let pane_current_path = pane.as_ref().map(|pane| {
+    let execution = exec_command(format!("tmux display-message -p -t {} #{{pane_current_path}}", pane));
+
+    String::from_utf8_lossy(&execution.stdout).trim_end().to_string()
+  });
+
+  let options = state::Options {
+    regexp,
+    prefixes,
+    normalizers,
+  };
+  let mut state = state::State::new(&lines, alphabet.letters(), options);
+
+  let mut failures: Vec<String> = Vec::new();
+  let mut pick = |picked: &view::Pick| {
+    let text = picked.texts.join(&multi_separator);
+
+    if osc52 {
+      let base64_text = base64::encode(text.as_bytes());
+      print!("\x1bPtmux;\x1b\x1b]52;c;{}\x1b\x1b\\\\\x1b\\\n", base64_text);
+    }
+
+    let pattern = picked.pattern.as_deref();
+
+    let operator_command = match picked.operator {
+      operators::Operator::Copy | operators::Operator::Paste => {
+        command_for(&pattern_commands, pattern, &command_template)
+      }
+      operators::Operator::Open => &open_template,
+      operators::Operator::Edit => &edit_template,
+      operators::Operator::Send => &send_template,
+    };
+
+    let context = commands::Context::new(
+      &text,
+      pattern,
+      picked.position,
+      pane.as_deref(),
+      pane_current_path.as_deref(),
+    );
+
+    let result = operator_command.execute(&context).and_then(|_| {
+      if picked.operator == operators::Operator::Paste {
+        let upcase_command = command_for(&pattern_upcase_commands, pattern, &upcase_template);
+
+        upcase_command.execute(&context)
+      } else {
+        Ok(())
+      }
+    });
+
+    if let Err(error) = result {
+      failures.push(error);
+    }
+  };
 
   let selected = {
-    let mut viewbox = view::View::new(
-      &mut state,
+    let options = view::Options {
       reverse,
       unique,
       contrast,
@@ -183,27 +521,33 @@ fn main() {
       background_color,
       hint_foreground_color,
       hint_background_color,
-      copy_mode_up_key,
-      copy_mode_down_key,
-    );
+      copy_mode_cursor_up_key: copy_mode_up_key,
+      copy_mode_cursor_down_key: copy_mode_down_key,
+      upcase_modifier,
+      keymap,
+      multi,
+      sticky,
+      adjust,
+      granularity,
+      status,
+      command,
+      upcase_command,
+      pattern_commands: named_commands,
+      pattern_upcase_commands: named_upcase_commands,
+      open_command,
+      edit_command,
+      send_command,
+    };
+    let mut viewbox = view::View::new(&mut state, options);
 
-    viewbox.present()
+    viewbox.present(&mut pick)
   };
 
   let mut copy_mode_movement: Option<String> = None;
-  if let Some((text, paste, movement)) = selected {
-    if osc52 {
-      let base64_text = base64::encode(text.as_bytes());
-      print!("\x1bPtmux;\x1b\x1b]52;c;{}\x1b\x1b\\\\\x1b\\\n", base64_text);
-    }
-
-    exec_command(str::replace(command, "{}", text.as_str()));
-
-    if paste {
-      exec_command(upcase_command.to_string());
-    }
+  if let Some(picked) = selected {
+    pick(&picked);
 
-    copy_mode_movement = movement;
+    copy_mode_movement = picked.movement;
   }
 
   if let Some(pane) = args.value_of("tmux_pane") {
@@ -214,4 +558,10 @@ fn main() {
       exec_command(format!("tmux send-keys -t {} -X {}", pane, movement));
     }
   };
+
+  if !failures.is_empty() {
+    report(&failures, args.value_of("tmux_pane"));
+
+    std::process::exit(1);
+  }
 }
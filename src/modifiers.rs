@@ -0,0 +1,122 @@
+// How the user asks for the upcase command when picking a hint
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Modifier {
+  // Typing the last letter of the hint in upcase
+  #[default]
+  Shift,
+  // Holding Ctrl while typing the last letter of the hint
+  Ctrl,
+  // Confirming the typed hint with Enter
+  Enter,
+  // Typing this key before the hint
+  Leader(char),
+}
+
+impl Modifier {
+  // Whether the alphabet can use upcase letters without clashing with it.
+  // Ctrl with Shift arrives as the Ctrl lowercase letter.
+  pub fn allows_upcase(&self) -> bool {
+    *self != Modifier::Shift && *self != Modifier::Ctrl
+  }
+
+  // termbox only reports Ctrl with the ASCII letters, and Ctrl-i and Ctrl-m
+  // arrive as Tab and Enter, so other letters can't end a hint picked with Ctrl
+  pub fn validate(&self, letters: &str) -> Result<(), String> {
+    let typeable = |letter: char| letter.is_ascii_lowercase() && letter != 'i' && letter != 'm';
+
+    match letters.chars().find(|&letter| *self == Modifier::Ctrl && !typeable(letter)) {
+      Some(letter) => Err(format!("Letter {} can't be typed with ctrl", letter)),
+      None => Ok(()),
+    }
+  }
+
+  // Letter of the hint typed with a key, and whether the upcase command is
+  // asked after it. Typing the leader again takes it back.
+  pub fn letter(&self, ch: char, typed_hint: &str, upcase: bool) -> (Option<char>, bool) {
+    match *self {
+      Modifier::Leader(key) if key == ch && typed_hint.is_empty() => (None, !upcase),
+      Modifier::Leader(_) => (Some(ch), upcase),
+      Modifier::Shift => (ch.to_lowercase().next(), ch.is_uppercase()),
+      _ => (Some(ch), false),
+    }
+  }
+
+  // How to trigger the upcase command, as shown in the help
+  pub fn description(&self) -> String {
+    match *self {
+      Modifier::Shift => "type the last letter of the hint in upcase".to_string(),
+      Modifier::Ctrl => "hold Ctrl while typing the last letter of the hint".to_string(),
+      Modifier::Enter => "confirm the typed hint with Enter".to_string(),
+      Modifier::Leader(key) => format!("type {} before the hint", key),
+    }
+  }
+
+  pub fn reserved_key(&self) -> Option<char> {
+    match *self {
+      Modifier::Leader(key) => Some(key),
+      _ => None,
+    }
+  }
+}
+
+pub fn get_modifier(modifier_name: &str) -> Result<Modifier, String> {
+  let mut chars = modifier_name.chars();
+
+  match (modifier_name, chars.next(), chars.next()) {
+    ("shift", _, _) => Ok(Modifier::Shift),
+    ("ctrl", _, _) => Ok(Modifier::Ctrl),
+    ("enter", _, _) => Ok(Modifier::Enter),
+    (_, Some(key), None) if !key.is_alphanumeric() && !key.is_whitespace() => {
+      Ok(Modifier::Leader(key))
+    }
+    _ => Err(format!("Unknown upcase modifier: {}", modifier_name)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::alphabets;
+
+  #[test]
+  fn match_modifier() {
+    assert_eq!(get_modifier("shift"), Ok(Modifier::Shift));
+    assert_eq!(get_modifier("ctrl"), Ok(Modifier::Ctrl));
+    assert_eq!(get_modifier("enter"), Ok(Modifier::Enter));
+    assert_eq!(get_modifier(";"), Ok(Modifier::Leader(';')));
+    assert!(get_modifier("a").is_err());
+    assert!(get_modifier("alt").is_err());
+  }
+
+  #[test]
+  fn ctrl_letters() {
+    assert!(!Modifier::Ctrl.allows_upcase());
+    assert!(Modifier::Enter.allows_upcase());
+    assert!(Modifier::Ctrl.validate("asdf").is_ok());
+    assert!(Modifier::Ctrl.validate("asdfi").is_err());
+    assert!(Modifier::Ctrl.validate("m").is_err());
+    assert!(Modifier::Ctrl.validate("[;").is_err());
+    assert!(Modifier::Ctrl.validate("asdfA").is_err());
+    assert!(Modifier::Shift.validate("asdfim").is_ok());
+    assert!(Modifier::Shift.validate("1234567890").is_ok());
+
+    for name in &["numeric", "russian-jcuken-homerow"] {
+      let alphabet = alphabets::get_alphabet(name, None).unwrap();
+
+      assert!(Modifier::Ctrl.validate(alphabet.letters()).is_err());
+    }
+  }
+
+  #[test]
+  fn typed_letters() {
+    let leader = Modifier::Leader(';');
+
+    assert_eq!(leader.letter(';', "", false), (None, true));
+    assert_eq!(leader.letter('a', "", true), (Some('a'), true));
+    assert_eq!(leader.letter(';', "", true), (None, false));
+    assert_eq!(leader.letter(';', "a", true), (Some(';'), true));
+    assert_eq!(Modifier::Shift.letter('A', "", false), (Some('a'), true));
+    assert_eq!(Modifier::Shift.letter('a', "", true), (Some('a'), false));
+    assert_eq!(Modifier::Enter.letter('a', "", true), (Some('a'), false));
+  }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalizer {
+  Case,
+  TrailingSlash,
+  Url,
+  Path,
+}
+
+const NORMALIZERS: [(&str, Normalizer); 4] = [
+  ("case", Normalizer::Case),
+  ("trailing-slash", Normalizer::TrailingSlash),
+  ("url", Normalizer::Url),
+  ("path", Normalizer::Path),
+];
+
+const HEX_PATTERNS: [&str; 5] = ["color", "uid", "sha", "ipv6", "address"];
+const URL_PATTERNS: [&str; 2] = ["markdown_url", "url"];
+const PATH_PATTERNS: [&str; 3] = ["diff_a", "diff_b", "path"];
+
+impl Normalizer {
+  pub fn apply(&self, pattern: &str, text: &str) -> String {
+    match *self {
+      Normalizer::Case if HEX_PATTERNS.contains(&pattern) => text.to_lowercase(),
+      Normalizer::TrailingSlash => trim_trailing_slash(text).to_string(),
+      Normalizer::Url if URL_PATTERNS.contains(&pattern) => canonical_url(text),
+      Normalizer::Path if PATH_PATTERNS.contains(&pattern) => resolve_path(text),
+      _ => text.to_string(),
+    }
+  }
+}
+
+fn trim_trailing_slash(text: &str) -> &str {
+  let trimmed = text.trim_end_matches('/');
+
+  if trimmed.is_empty() {
+    text
+  } else {
+    trimmed
+  }
+}
+
+// Lowercases scheme and host, and drops default ports and empty paths,
+// queries or fragments
+fn canonical_url(text: &str) -> String {
+  let (scheme, rest) = match text.find("://") {
+    Some(index) => (text[..index].to_lowercase(), &text[index + 3..]),
+    None => return text.to_string(),
+  };
+
+  let split = rest.find(&['/', '?', '#'][..]).unwrap_or(rest.len());
+  let mut host = rest[..split].to_lowercase();
+  let mut tail = rest[split..].to_string();
+
+  let default_port = match scheme.as_str() {
+    "http" => Some(":80"),
+    "https" => Some(":443"),
+    "ftp" => Some(":21"),
+    "ssh" => Some(":22"),
+    _ => None,
+  };
+
+  if let Some(port) = default_port {
+    if host.ends_with(port) {
+      let length = host.len() - port.len();
+      host.truncate(length);
+    }
+  }
+
+  while tail.ends_with('#') || tail.ends_with('?') {
+    tail.pop();
+  }
+
+  if tail == "/" {
+    tail.clear();
+  }
+
+  format!("{}://{}{}", scheme, host, tail)
+}
+
+// Resolves `.` and `..` segments and duplicated slashes without touching the
+// file system
+fn resolve_path(text: &str) -> String {
+  let absolute = text.starts_with('/');
+  let mut segments: Vec<&str> = Vec::new();
+
+  for segment in text.split('/') {
+    match segment {
+      "" | "." => {}
+      ".." => match segments.last() {
+        Some(&last) if last != ".." => {
+          segments.pop();
+        }
+        _ if absolute => {}
+        _ => segments.push(segment),
+      },
+      _ => segments.push(segment),
+    }
+  }
+
+  let path = segments.join("/");
+
+  match (absolute, path.is_empty()) {
+    (true, _) => format!("/{}", path),
+    (false, true) => ".".to_string(),
+    (false, false) => path,
+  }
+}
+
+pub fn normalize(normalizers: &[Normalizer], pattern: &str, text: &str) -> String {
+  normalizers
+    .iter()
+    .fold(text.to_string(), |text, normalizer| normalizer.apply(pattern, &text))
+}
+
+pub fn get_normalizer(normalizer_name: &str) -> Result<Normalizer, String> {
+  let available_normalizers: HashMap<&str, Normalizer> = NORMALIZERS.iter().cloned().collect();
+
+  available_normalizers
+    .get(normalizer_name)
+    .cloned()
+    .ok_or_else(|| format!("Unknown normalizer: {}", normalizer_name))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn match_normalizer() {
+    assert_eq!(get_normalizer("url"), Ok(Normalizer::Url));
+    assert!(get_normalizer("unknown").is_err());
+  }
+
+  #[test]
+  fn normalize_case() {
+    assert_eq!(Normalizer::Case.apply("sha", "FD70B56"), "fd70b56");
+    assert_eq!(Normalizer::Case.apply("path", "/Tmp/Foo"), "/Tmp/Foo");
+  }
+
+  #[test]
+  fn normalize_trailing_slash() {
+    assert_eq!(Normalizer::TrailingSlash.apply("path", "/tmp/foo/"), "/tmp/foo");
+    assert_eq!(Normalizer::TrailingSlash.apply("path", "/"), "/");
+  }
+
+  #[test]
+  fn normalize_url() {
+    assert_eq!(Normalizer::Url.apply("url", "HTTPS://X.io/"), "https://x.io");
+    assert_eq!(Normalizer::Url.apply("url", "http://x.io:80/a?"), "http://x.io/a");
+    assert_eq!(Normalizer::Url.apply("url", "https://x.io:8080/A#"), "https://x.io:8080/A");
+  }
+
+  #[test]
+  fn normalize_path() {
+    assert_eq!(Normalizer::Path.apply("path", "/tmp//foo/./bar/../baz"), "/tmp/foo/baz");
+    assert_eq!(Normalizer::Path.apply("path", "../log/../kern.log"), "../kern.log");
+    assert_eq!(Normalizer::Path.apply("path", "/../foo/"), "/foo");
+  }
+}
@@ -0,0 +1,11 @@
+// What to do with the picked text, chosen typing a key before the hint
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+  // Run the command
+  Copy,
+  // Run the command and then the upcase command
+  Paste,
+  Open,
+  Edit,
+  Send,
+}
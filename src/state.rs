@@ -1,4 +1,7 @@
+use super::granularities::Granularity;
+use super::normalizers::{normalize, Normalizer};
 use regex::Regex;
+use unicode_width::UnicodeWidthChar;
 use std::collections::HashMap;
 use std::fmt;
 
@@ -27,6 +30,9 @@ const PATTERNS: [(&'static str, &'static str); 13] = [
   ("number", r"[0-9]{4,}"),
 ];
 
+const WORD_PATTERN: &str = r"[^\s[:cntrl:]]+";
+const LINE_PATTERN: &str = r"[^\s[:cntrl:]](?:.*\S)?";
+
 #[derive(Clone)]
 pub struct Match<'a> {
   pub x: i32,
@@ -56,33 +62,137 @@ impl<'a> PartialEq for Match<'a> {
   }
 }
 
+// Removes color sequences
+pub fn clean(text: &str) -> String {
+  if !text.chars().any(|c| c.is_control()) {
+    return text.to_string();
+  }
+
+  let exclude = Regex::new(EXCLUDE_PATTERNS[0].1).unwrap();
+
+  exclude.replace_all(text, "").to_string()
+}
+
+// Display column of a byte offset among the cells of a line
+fn column(cells: &[(usize, usize, char)], offset: usize) -> usize {
+  match cells.iter().find(|cell| cell.0 >= offset) {
+    Some(cell) => cell.1,
+    None => cells
+      .last()
+      .map(|cell| cell.1 + cell.2.width().unwrap_or(0))
+      .unwrap_or(0),
+  }
+}
+
+fn fuzzy_match(text: &str, query: &str) -> bool {
+  let mut chars = text.chars().flat_map(|c| c.to_lowercase());
+
+  query
+    .chars()
+    .flat_map(|c| c.to_lowercase())
+    .all(|q| chars.any(|c| c == q))
+}
+
+// Prefix letters have to belong to a known pattern and be typeable as the
+// first letter of a hint
+// Patterns named in an option must be built-in or named custom ones
+pub fn validate_patterns(patterns: &[&str], regexp: &[(&str, &str)], option: &str) -> Result<(), String> {
+  let known = |pattern: &str| {
+    PATTERNS.iter().any(|&(name, _)| name == pattern) || regexp.iter().any(|&(name, _)| name == pattern)
+  };
+
+  match patterns.iter().find(|pattern| !known(pattern)) {
+    Some(pattern) => Err(format!("Unknown pattern in {}: {}", option, pattern)),
+    None => Ok(()),
+  }
+}
+
+pub fn validate_prefixes(
+  prefixes: &[(&str, char)],
+  regexp: &[(&str, &str)],
+  reserved: &[char],
+  allow_upcase: bool,
+) -> Result<(), String> {
+  let patterns = prefixes.iter().map(|&(pattern, _)| pattern).collect::<Vec<_>>();
+
+  validate_patterns(&patterns, regexp, "prefix")?;
+
+  for &(_, letter) in prefixes {
+    if letter.is_control() || letter.is_whitespace() {
+      return Err(format!("Control key in prefix: {:?}", letter));
+    }
+
+    if letter.is_uppercase() && !allow_upcase {
+      return Err(format!("Upcase letter in prefix: {}", letter));
+    }
+
+    if reserved.contains(&letter) {
+      return Err(format!("Reserved key in prefix: {}", letter));
+    }
+  }
+
+  Ok(())
+}
+
+// How matches are found and hinted, besides the alphabet
+#[derive(Default)]
+pub struct Options<'a> {
+  pub regexp: Vec<(&'a str, &'a str)>,
+  pub prefixes: Vec<(&'a str, char)>,
+  pub normalizers: Vec<Normalizer>,
+}
+
 pub struct State<'a> {
   pub lines: &'a Vec<&'a str>,
   alphabet: &'a str,
-  regexp: &'a Vec<&'a str>,
+  options: Options<'a>,
+  rows: usize,
 }
 
 impl<'a> State<'a> {
-  pub fn new(lines: &'a Vec<&'a str>, alphabet: &'a str, regexp: &'a Vec<&'a str>) -> State<'a> {
+  pub fn new(lines: &'a Vec<&'a str>, alphabet: &'a str, options: Options<'a>) -> State<'a> {
     State {
       lines: lines,
       alphabet: alphabet,
-      regexp: regexp,
+      options,
+      rows: lines.len(),
     }
   }
 
-  pub fn matches(&self, reverse: bool, unique: bool) -> Vec<Match<'a>> {
-    let mut matches = Vec::new();
+  // Lines from a row on are left unmatched, when something is drawn over them
+  pub fn hide_from(&mut self, row: usize) {
+    self.rows = self.rows.min(row);
+  }
+
+  pub fn alphabet(&self) -> &str {
+    self.alphabet
+  }
+
+  // Names of the matched patterns, custom ones first as they take precedence
+  pub fn patterns(&self) -> Vec<String> {
+    self
+      .options
+      .regexp
+      .iter()
+      .map(|&(name, regexp)| match name {
+        "custom" => format!("custom {}", regexp),
+        _ => name.to_string(),
+      })
+      .chain(PATTERNS.iter().map(|tuple| tuple.0.to_string()))
+      .collect()
+  }
 
+  pub fn matches(&self, reverse: bool, unique: bool) -> Vec<Match<'a>> {
     let exclude_patterns = EXCLUDE_PATTERNS
       .iter()
       .map(|tuple| (tuple.0, Regex::new(tuple.1).unwrap()))
       .collect::<Vec<_>>();
 
     let custom_patterns = self
+      .options
       .regexp
       .iter()
-      .map(|regexp| ("custom", Regex::new(regexp).expect("Invalid custom regexp")))
+      .map(|&(name, regexp)| (name, Regex::new(regexp).expect("Invalid custom regexp")))
       .collect::<Vec<_>>();
 
     let patterns = PATTERNS
@@ -91,17 +201,89 @@ impl<'a> State<'a> {
       .collect::<Vec<_>>();
 
     let all_patterns = [exclude_patterns, custom_patterns, patterns].concat();
+    let mut matches = self.scan(&all_patterns);
+
+    self.hint_matches(&mut matches, reverse, unique);
+
+    return matches;
+  }
 
-    for (index, line) in self.lines.iter().enumerate() {
+  // Hints every word, line or paragraph instead of the known patterns
+  pub fn units(&self, granularity: Granularity, reverse: bool, unique: bool) -> Vec<Match<'a>> {
+    let (name, pattern) = match granularity {
+      Granularity::Pattern => return self.matches(reverse, unique),
+      Granularity::Word => ("word", WORD_PATTERN),
+      Granularity::Line => ("line", LINE_PATTERN),
+      Granularity::Paragraph => ("paragraph", LINE_PATTERN),
+    };
+
+    let mut all_patterns = EXCLUDE_PATTERNS
+      .iter()
+      .map(|tuple| (tuple.0, Regex::new(tuple.1).unwrap()))
+      .collect::<Vec<_>>();
+
+    all_patterns.push((name, Regex::new(pattern).unwrap()));
+
+    let mut matches = self.scan(&all_patterns);
+
+    // Paragraphs are hinted in their first line
+    if granularity == Granularity::Paragraph {
+      matches.retain(|mat| mat.y == 0 || self.blank(mat.y as usize - 1));
+    }
+
+    self.hint_matches(&mut matches, reverse, unique);
+
+    matches
+  }
+
+  fn blank(&self, y: usize) -> bool {
+    clean(self.lines[y]).trim().is_empty()
+  }
+
+  // Text picked for a match, without color sequences
+  pub fn text(&self, mat: &Match) -> String {
+    if mat.pattern != "paragraph" {
+      return clean(mat.text);
+    }
+
+    let mut last = mat.y as usize;
+
+    while last + 1 < self.lines.len() && !self.blank(last + 1) {
+      last += 1;
+    }
+
+    if last == mat.y as usize {
+      return clean(mat.text);
+    }
+
+    let end = Match {
+      x: 0,
+      y: last as i32,
+      pattern: mat.pattern,
+      text: self.lines[last].trim_end(),
+      hint: None,
+    };
+
+    self.range(mat, &end)
+  }
+
+  fn scan(&self, all_patterns: &[(&'a str, Regex)]) -> Vec<Match<'a>> {
+    let mut matches = Vec::new();
+
+    for (index, line) in self.lines.iter().take(self.rows).enumerate() {
       let mut chunk: &str = line;
       let mut offset: i32 = 0;
 
       loop {
+        // Empty matches are skipped, as they would never move forward
         let submatches = all_patterns
           .iter()
-          .filter_map(|tuple| match tuple.1.find_iter(chunk).nth(0) {
-            Some(m) => Some((tuple.0, tuple.1.clone(), m)),
-            None => None,
+          .filter_map(|tuple| {
+            tuple
+              .1
+              .find_iter(chunk)
+              .find(|m| m.start() != m.end())
+              .map(|m| (tuple.0, tuple.1.clone(), m))
           })
           .collect::<Vec<_>>();
         let first_match_option = submatches
@@ -112,70 +294,266 @@ impl<'a> State<'a> {
           let (name, pattern, matching) = first_match;
           let text = matching.as_str();
 
-          if let Some(captures) = pattern.captures(text) {
-            let (subtext, substart) = if let Some(capture) = captures.get(1) {
-              (capture.as_str(), capture.start())
-            } else {
-              (matching.as_str(), 0)
-            };
-
-            // Never hint or broke bash color sequences
-            if *name != "bash" {
-              matches.push(Match {
-                x: offset + matching.start() as i32 + substart as i32,
-                y: index as i32,
-                pattern: name,
-                text: subtext,
-                hint: None,
-              });
-            }
-
-            chunk = chunk.get(matching.end()..).expect("Unknown chunk");
-            offset = offset + matching.end() as i32;
-          } else {
-            panic!("No matching?");
+          let capture = pattern.captures(text).and_then(|captures| captures.get(1));
+          let (subtext, substart) = match capture {
+            Some(capture) => (capture.as_str(), capture.start()),
+            None => (text, 0),
+          };
+
+          // Never hint or broke bash color sequences
+          if *name != "bash" {
+            matches.push(Match {
+              x: offset + matching.start() as i32 + substart as i32,
+              y: index as i32,
+              pattern: name,
+              text: subtext,
+              hint: None,
+            });
           }
+
+          chunk = chunk.get(matching.end()..).expect("Unknown chunk");
+          offset = offset + matching.end() as i32;
         } else {
           break;
         }
       }
     }
 
-    let alphabet = super::alphabets::get_alphabet(self.alphabet);
-    let mut hints = alphabet.hints(matches.len());
+    matches
+  }
+
+  // Hints every occurrence of an ad-hoc pattern typed in the overlay. Queries
+  // that aren't valid regexps are searched literally.
+  pub fn search(&self, query: &str, reverse: bool, unique: bool) -> Vec<Match<'a>> {
+    let regexp = Regex::new(query)
+      .or_else(|_| Regex::new(&regex::escape(query)))
+      .unwrap();
+
+    let mut all_patterns = EXCLUDE_PATTERNS
+      .iter()
+      .map(|tuple| (tuple.0, Regex::new(tuple.1).unwrap()))
+      .collect::<Vec<_>>();
+
+    all_patterns.push(("search", regexp));
 
-    // This looks wrong but we do a pop after
-    if !reverse {
-      hints.reverse();
+    let mut matches = self.scan(&all_patterns);
+
+    self.hint_matches(&mut matches, reverse, unique);
+
+    matches
+  }
+
+  // Text on screen from the start of a match to the end of another one,
+  // without color sequences
+  pub fn range(&self, from: &Match, to: &Match) -> String {
+    let (first, last) = if (to.y, to.x) < (from.y, from.x) {
+      (to, from)
     } else {
+      (from, to)
+    };
+
+    let exclude = Regex::new(EXCLUDE_PATTERNS[0].1).unwrap();
+    let end = last.x as usize + last.text.len();
+
+    (first.y..last.y + 1)
+      .map(|y| {
+        let line = self.lines[y as usize];
+        let start = if y == first.y { first.x as usize } else { 0 };
+        let stop = if y == last.y { end } else { line.len() };
+
+        exclude.replace_all(&line[start..stop], "").trim_end().to_string()
+      })
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+
+  // Byte offset and display column of every char of a line, skipping color
+  // sequences
+  pub fn cells(&self, y: usize) -> Vec<(usize, usize, char)> {
+    let line = self.lines[y];
+    let exclude = Regex::new(EXCLUDE_PATTERNS[0].1).unwrap();
+    let sequences = exclude.find_iter(line).collect::<Vec<_>>();
+    let mut position = 0;
+
+    line
+      .char_indices()
+      .filter(|&(offset, _)| {
+        !sequences
+          .iter()
+          .any(|sequence| offset >= sequence.start() && offset < sequence.end())
+      })
+      .map(|(offset, c)| {
+        let cell = (offset, position, c);
+        position += c.width().unwrap_or(0);
+        cell
+      })
+      .collect()
+  }
+
+  // Line and display column where a match starts
+  pub fn position(&self, mat: &Match) -> (usize, usize) {
+    let y = mat.y as usize;
+
+    (y, column(&self.cells(y), mat.x as usize))
+  }
+
+  // Columns between two matches used as opposite corners of a rectangle
+  pub fn block(&self, from: &Match, to: &Match) -> String {
+    let top = from.y.min(to.y) as usize;
+    let bottom = from.y.max(to.y) as usize;
+
+    let bounds = |mat: &Match| {
+      let cells = self.cells(mat.y as usize);
+      let start = mat.x as usize;
+
+      (column(&cells, start), column(&cells, start + mat.text.len()))
+    };
+
+    let (from_left, from_right) = bounds(from);
+    let (to_left, to_right) = bounds(to);
+    let left = from_left.min(to_left);
+    let right = from_right.max(to_right);
+
+    (top..bottom + 1)
+      .map(|y| {
+        self
+          .cells(y)
+          .iter()
+          .filter(|cell| cell.1 >= left && cell.1 < right)
+          .map(|cell| cell.2)
+          .collect::<String>()
+          .trim_end()
+          .to_string()
+      })
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+
+  // Keeps the matches that contain all the query characters in order and
+  // assigns them new hints
+  pub fn filter(
+    &self,
+    matches: &Vec<Match<'a>>,
+    query: &str,
+    reverse: bool,
+    unique: bool,
+  ) -> Vec<Match<'a>> {
+    let mut survivors = matches
+      .iter()
+      .filter(|mat| fuzzy_match(mat.text, query))
+      .map(|mat| Match {
+        hint: None,
+        ..mat.clone()
+      })
+      .collect::<Vec<_>>();
+
+    self.hint_matches(&mut survivors, reverse, unique);
+
+    survivors
+  }
+
+  fn hint_matches(&self, matches: &mut Vec<Match<'a>>, reverse: bool, unique: bool) {
+    if reverse {
       matches.reverse();
-      hints.reverse();
     }
 
-    if unique {
-      let mut previous: HashMap<&str, String> = HashMap::new();
+    self.assign_hints(matches, unique);
+
+    if reverse {
+      matches.reverse();
+    }
+  }
+
+  // Matches of a pattern with a reserved prefix letter get their hints from
+  // that letter pool. When the pool is exhausted they fall back to the shared
+  // pool, which never uses any reserved letter as first character.
+  fn assign_hints(&self, matches: &mut Vec<Match<'a>>, unique: bool) {
+    let alphabet = super::alphabets::Alphabet::new(self.alphabet);
+    let reserved = self
+      .options
+      .prefixes
+      .iter()
+      .map(|&(_, letter)| letter)
+      .collect::<Vec<char>>();
 
-      for mat in &mut matches {
-        if let Some(previous_hint) = previous.get(mat.text) {
+    let pools = matches
+      .iter()
+      .map(|mat| {
+        self
+          .options
+          .prefixes
+          .iter()
+          .find(|&&(pattern, _)| pattern == mat.pattern)
+          .map(|&(_, letter)| letter)
+      })
+      .collect::<Vec<_>>();
+
+    // Duplicated matches are grouped by their normalized text, which is the
+    // one of all their lines for paragraphs
+    let keys = matches
+      .iter()
+      .map(|mat| match mat.pattern {
+        "paragraph" => normalize(&self.options.normalizers, mat.pattern, &self.text(mat)),
+        _ => normalize(&self.options.normalizers, mat.pattern, mat.text),
+      })
+      .collect::<Vec<_>>();
+
+    let mut demand: HashMap<Option<char>, Vec<&str>> = HashMap::new();
+
+    for (key, pool) in keys.iter().zip(pools.iter()) {
+      let texts = demand.entry(*pool).or_default();
+
+      if !unique || !texts.contains(&key.as_str()) {
+        texts.push(key);
+      }
+    }
+
+    let mut overflow = 0;
+    let mut prefixed_hints: HashMap<char, Vec<String>> = HashMap::new();
+
+    for (pool, texts) in demand.iter() {
+      if let Some(letter) = pool {
+        let count = texts.len().min(alphabet.capacity());
+        let mut hints = alphabet
+          .hints(count)
+          .iter()
+          .map(|hint| format!("{}{}", letter, hint))
+          .collect::<Vec<_>>();
+
+        // This looks wrong but we do a pop after
+        hints.reverse();
+        overflow = overflow + texts.len() - count;
+        prefixed_hints.insert(*letter, hints);
+      }
+    }
+
+    let shared = demand.get(&None).map(|texts| texts.len()).unwrap_or(0);
+    let mut hints = alphabet.without(&reserved).hints(shared + overflow);
+    hints.reverse();
+
+    let mut previous: HashMap<&str, String> = HashMap::new();
+
+    for ((mat, pool), key) in matches.iter_mut().zip(pools.iter()).zip(keys.iter()) {
+      if unique {
+        if let Some(previous_hint) = previous.get(key.as_str()) {
           mat.hint = Some(previous_hint.clone());
-        } else if let Some(hint) = hints.pop() {
-          mat.hint = Some(hint.to_string().clone());
-          previous.insert(mat.text, hint.to_string().clone());
+          continue;
         }
       }
-    } else {
-      for mat in &mut matches {
-        if let Some(hint) = hints.pop() {
-          mat.hint = Some(hint.to_string().clone());
+
+      let hint = pool
+        .and_then(|letter| prefixed_hints.get_mut(&letter))
+        .and_then(|hints| hints.pop())
+        .or_else(|| hints.pop());
+
+      if let Some(hint) = hint {
+        if unique {
+          previous.insert(key, hint.clone());
         }
-      }
-    }
 
-    if reverse {
-      matches.reverse();
+        mat.hint = Some(hint);
+      }
     }
-
-    return matches;
   }
 }
 
@@ -187,11 +565,14 @@ mod tests {
     output.split("\n").collect::<Vec<&str>>()
   }
 
+  fn state<'a>(lines: &'a Vec<&'a str>) -> State<'a> {
+    State::new(lines, "abcd", Options::default())
+  }
+
   #[test]
   fn match_reverse() {
     let lines = split("lorem 127.0.0.1 lorem 255.255.255.255 lorem 127.0.0.1 lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 3);
     assert_eq!(results.first().unwrap().hint.clone().unwrap(), "a");
@@ -201,21 +582,232 @@ mod tests {
   #[test]
   fn match_unique() {
     let lines = split("lorem 127.0.0.1 lorem 255.255.255.255 lorem 127.0.0.1 lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, true);
+    let results = state(&lines).matches(false, true);
 
     assert_eq!(results.len(), 3);
     assert_eq!(results.first().unwrap().hint.clone().unwrap(), "a");
     assert_eq!(results.last().unwrap().hint.clone().unwrap(), "a");
   }
 
+  #[test]
+  fn match_unique_normalized() {
+    let lines = split("lorem https://x.io/ lorem https://X.io /tmp/foo/ /tmp/./foo #FD70B5 #fd70b5");
+    let options = Options {
+      normalizers: [Normalizer::Case, Normalizer::Url, Normalizer::Path].to_vec(),
+      ..Options::default()
+    };
+    let results = State::new(&lines, "abcd", options).matches(false, true);
+
+    assert_eq!(results.len(), 6);
+    assert_eq!(results.get(0).unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.get(1).unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.get(2).unwrap().hint.clone().unwrap(), "b");
+    assert_eq!(results.get(3).unwrap().hint.clone().unwrap(), "b");
+    assert_eq!(results.get(4).unwrap().hint.clone().unwrap(), "c");
+    assert_eq!(results.get(5).unwrap().hint.clone().unwrap(), "c");
+  }
+
+  #[test]
+  fn filter_matches() {
+    let lines = split("lorem /var/log/nginx.log lorem /tmp/foo lorem /var/log/kern.log");
+    let state = state(&lines);
+    let results = state.filter(&state.matches(false, false), "VLK", false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.get(0).unwrap().text, "/var/log/kern.log");
+    assert_eq!(results.get(0).unwrap().hint.clone().unwrap(), "a");
+
+    let results = state.filter(&state.matches(false, false), "log", false, false);
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results.get(1).unwrap().hint.clone().unwrap(), "b");
+  }
+
+  #[test]
+  fn search_matches() {
+    let lines = split("lorem ipsum lorem\n Lorem \x1b[32mlorem\x1b[m ipsum");
+    let state = state(&lines);
+    let results = state.search("[lL]orem", false, false);
+
+    assert_eq!(results.len(), 4);
+    assert_eq!(results.get(3).unwrap().text, "lorem");
+    assert_eq!(results.get(3).unwrap().x, 12);
+    assert_eq!(results.get(3).unwrap().pattern, "search");
+    assert_eq!(results.get(3).unwrap().hint.clone().unwrap(), "d");
+
+    assert_eq!(state.search("[32", false, false).len(), 0);
+    assert_eq!(state.search("x*", false, false).len(), 0);
+    assert_eq!(state.search("\\b", false, false).len(), 0);
+    assert_eq!(state.search("\\bip", false, false).len(), 2);
+    assert_eq!(state.search("o*r", false, false).len(), 4);
+  }
+
+  #[test]
+  fn hidden_lines() {
+    let lines = split("lorem /tmp/foo\n/tmp/bar lorem");
+    let mut state = state(&lines);
+
+    state.hide_from(1);
+
+    let results = state.matches(false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.get(0).unwrap().text, "/tmp/foo");
+    assert_eq!(state.search("lorem", false, false).len(), 1);
+  }
+
+  #[test]
+  fn range_matches() {
+    let lines = split("error: /tmp/foo failed  \n  at \x1b[32m/tmp/bar\x1b[m line 127.0.0.1 lorem");
+    let state = state(&lines);
+    let results = state.matches(false, false);
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(
+      state.range(&results[2], &results[0]),
+      "/tmp/foo failed\n  at /tmp/bar line 127.0.0.1"
+    );
+    assert_eq!(state.range(&results[1], &results[1]), "/tmp/bar");
+  }
+
+  #[test]
+  fn match_positions() {
+    let lines = split("lorem /tmp/a\n\x1b[32mλλ\x1b[m /tmp/b");
+    let state = state(&lines);
+    let results = state.matches(false, false);
+
+    assert_eq!(state.position(&results[0]), (0, 6));
+    assert_eq!(state.position(&results[1]), (1, 3));
+  }
+
+  #[test]
+  fn block_matches() {
+    let lines = split("name  ip         port\nfoo   127.0.0.1  8080\n\x1b[32mbär\x1b[m   10.0.0.2   9999\n日本  10.0.0.3   7777");
+    let state = state(&lines);
+    let results = state.matches(false, false);
+
+    assert_eq!(results.len(), 6);
+    assert_eq!(
+      state.block(&results[0], &results[5]),
+      "127.0.0.1  8080\n10.0.0.2   9999\n10.0.0.3   7777"
+    );
+    assert_eq!(
+      state.block(&results[4], &results[0]),
+      "127.0.0.1\n10.0.0.2\n10.0.0.3"
+    );
+  }
+
+  #[test]
+  fn word_units() {
+    let lines = split("lorem \x1b[32mipsum\x1b[m, dolor\n  sit");
+    let state = state(&lines);
+    let results = state.units(Granularity::Word, false, false);
+
+    assert_eq!(results.len(), 5);
+    assert_eq!(results.get(1).unwrap().text, "ipsum");
+    assert_eq!(results.get(2).unwrap().text, ",");
+    assert_eq!(results.get(4).unwrap().text, "sit");
+  }
+
+  #[test]
+  fn line_units() {
+    let lines = split("$ cargo test  \n\n  \x1b[31merror: failed\x1b[m\n");
+    let state = state(&lines);
+    let results = state.units(Granularity::Line, false, false);
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results.get(0).unwrap().text, "$ cargo test");
+    assert_eq!(state.text(results.get(1).unwrap()), "error: failed");
+  }
+
+  #[test]
+  fn paragraph_units() {
+    let lines = split("lorem ipsum\n  dolor sit\n\namet\n \nconsectetur\nadipiscing");
+    let state = state(&lines);
+    let results = state.units(Granularity::Paragraph, false, false);
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(state.text(results.get(0).unwrap()), "lorem ipsum\n  dolor sit");
+    assert_eq!(state.text(results.get(1).unwrap()), "amet");
+    assert_eq!(state.text(results.get(2).unwrap()), "consectetur\nadipiscing");
+  }
+
+  #[test]
+  fn paragraph_unique_units() {
+    let lines = split("lorem\nipsum\n\n  lorem\n\nlorem\nipsum");
+    let state = state(&lines);
+    let results = state.units(Granularity::Paragraph, false, true);
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(state.text(results.get(1).unwrap()), "lorem");
+    assert_eq!(results.get(0).unwrap().hint, results.get(2).unwrap().hint);
+    assert_ne!(results.get(0).unwrap().hint, results.get(1).unwrap().hint);
+  }
+
+  #[test]
+  fn match_prefixes() {
+    let lines = split("lorem https://github.io /tmp/foo lorem 127.0.0.1 https://crates.io 127.0.0.2");
+    let options = Options {
+      prefixes: [("url", 'u'), ("path", 'f')].to_vec(),
+      ..Options::default()
+    };
+    let results = State::new(&lines, "abcd", options).matches(false, false);
+
+    assert_eq!(results.len(), 5);
+    assert_eq!(results.get(0).unwrap().hint.clone().unwrap(), "ua");
+    assert_eq!(results.get(1).unwrap().hint.clone().unwrap(), "fa");
+    assert_eq!(results.get(2).unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.get(3).unwrap().hint.clone().unwrap(), "ub");
+    assert_eq!(results.get(4).unwrap().hint.clone().unwrap(), "b");
+  }
+
+  #[test]
+  fn match_prefixes_overflow() {
+    let output = format!("{} 127.0.0.1", vec!["https://crates.io"; 17].join(" "));
+    let lines = split(&output);
+    let options = Options {
+      prefixes: [("url", 'u')].to_vec(),
+      ..Options::default()
+    };
+    let results = State::new(&lines, "abcd", options).matches(false, false);
+
+    assert_eq!(results.len(), 18);
+    assert_eq!(results.get(0).unwrap().hint.clone().unwrap(), "uaa");
+    assert_eq!(results.get(15).unwrap().hint.clone().unwrap(), "udd");
+    assert_eq!(results.get(16).unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.get(17).unwrap().hint.clone().unwrap(), "b");
+  }
+
+  #[test]
+  fn validate_pattern_prefixes() {
+    let custom = [("ticket", "T-[0-9]+")].to_vec();
+
+    assert!(validate_prefixes(&[("url", 'u'), ("ticket", 't')], &custom, &['k'], false).is_ok());
+    assert!(validate_prefixes(&[("link", 'u')], &custom, &[], false).is_err());
+    assert!(validate_prefixes(&[("custom", 'c')], &custom, &[], false).is_err());
+    assert!(validate_prefixes(&[("url", 'k')], &custom, &['k'], false).is_err());
+    assert!(validate_prefixes(&[("url", 'U')], &custom, &[], false).is_err());
+    assert!(validate_prefixes(&[("url", 'U')], &custom, &[], true).is_ok());
+    assert!(validate_prefixes(&[("url", ' ')], &custom, &[], true).is_err());
+  }
+
+  #[test]
+  fn validate_pattern_names() {
+    let custom = [("ticket", "T-[0-9]+")].to_vec();
+
+    assert_eq!(validate_patterns(&["url", "ticket"], &custom, "command"), Ok(()));
+    assert_eq!(
+      validate_patterns(&["url", "ulr"], &custom, "command"),
+      Err("Unknown pattern in command: ulr".to_string())
+    );
+  }
+
   #[test]
   fn match_bash() {
     let lines = split(
       "path: [32m/var/log/nginx.log[m\npath: [32mtest/log/nginx-2.log:32[mfolder/.nginx@4df2.log",
     );
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 3);
     assert_eq!(results.get(0).unwrap().text, "/var/log/nginx.log");
@@ -228,8 +820,7 @@ mod tests {
     let lines = split(
       "Lorem /tmp/foo/bar_lol, lorem\n Lorem /var/log/boot-strap.log lorem ../log/kern.log lorem",
     );
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 3);
     assert_eq!(results.get(0).unwrap().text.clone(), "/tmp/foo/bar_lol");
@@ -243,8 +834,7 @@ mod tests {
   #[test]
   fn match_uids() {
     let lines = split("Lorem ipsum 123e4567-e89b-12d3-a456-426655440000 lorem\n Lorem lorem lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 1);
   }
@@ -252,8 +842,7 @@ mod tests {
   #[test]
   fn match_shas() {
     let lines = split("Lorem fd70b5695 5246ddf f924213 lorem\n Lorem 973113963b491874ab2e372ee60d4b4cb75f717c lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 4);
     assert_eq!(results.get(0).unwrap().text.clone(), "fd70b5695");
@@ -268,8 +857,7 @@ mod tests {
   #[test]
   fn match_ips() {
     let lines = split("Lorem ipsum 127.0.0.1 lorem\n Lorem 255.255.10.255 lorem 127.0.0.1 lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 3);
     assert_eq!(results.get(0).unwrap().text.clone(), "127.0.0.1");
@@ -280,8 +868,7 @@ mod tests {
   #[test]
   fn match_ipv6s() {
     let lines = split("Lorem ipsum fe80::2:202:fe4 lorem\n Lorem 2001:67c:670:202:7ba8:5e41:1591:d723 lorem fe80::2:1 lorem ipsum fe80:22:312:fe::1%eth0");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 4);
     assert_eq!(results.get(0).unwrap().text.clone(), "fe80::2:202:fe4");
@@ -300,8 +887,7 @@ mod tests {
   fn match_markdown_urls() {
     let lines =
       split("Lorem ipsum [link](https://github.io?foo=bar) ![](http://cdn.com/img.jpg) lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 2);
     assert_eq!(results.get(0).unwrap().pattern.clone(), "markdown_url");
@@ -319,8 +905,7 @@ mod tests {
   #[test]
   fn match_urls() {
     let lines = split("Lorem ipsum https://www.rust-lang.org/tools lorem\n Lorem ipsumhttps://crates.io lorem https://github.io?foo=bar lorem ssh://github.io");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 4);
     assert_eq!(
@@ -342,8 +927,7 @@ mod tests {
   #[test]
   fn match_addresses() {
     let lines = split("Lorem 0xfd70b5695 0x5246ddf lorem\n Lorem 0x973113tlorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 3);
     assert_eq!(results.get(0).unwrap().text.clone(), "0xfd70b5695");
@@ -354,8 +938,7 @@ mod tests {
   #[test]
   fn match_hex_colors() {
     let lines = split("Lorem #fd7b56 lorem #FF00FF\n Lorem #00fF05 lorem #abcd00 lorem #afRR00");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 4);
     assert_eq!(results.get(0).unwrap().text.clone(), "#fd7b56");
@@ -367,8 +950,7 @@ mod tests {
   #[test]
   fn match_ipfs() {
     let lines = split("Lorem QmRdbNSxDJBXmssAc9fvTtux4duptMvfSGiGuq6yHAQVKQ lorem Qmfoobar");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 1);
     assert_eq!(
@@ -380,8 +962,7 @@ mod tests {
   #[test]
   fn match_process_port() {
     let lines = split("Lorem 5695 52463 lorem\n Lorem 973113 lorem 99999 lorem 8888 lorem\n   23456 lorem 5432 lorem 23444");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 8);
   }
@@ -389,8 +970,7 @@ mod tests {
   #[test]
   fn match_diff_a() {
     let lines = split("Lorem lorem\n--- a/src/main.rs");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 1);
     assert_eq!(results.get(0).unwrap().text.clone(), "src/main.rs");
@@ -399,8 +979,7 @@ mod tests {
   #[test]
   fn match_diff_b() {
     let lines = split("Lorem lorem\n+++ b/src/main.rs");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = state(&lines).matches(false, false);
 
     assert_eq!(results.len(), 1);
     assert_eq!(results.get(0).unwrap().text.clone(), "src/main.rs");
@@ -409,13 +988,18 @@ mod tests {
   #[test]
   fn priority() {
     let lines = split("Lorem [link](http://foo.bar) ipsum CUSTOM-52463 lorem ISSUE-123 lorem\nLorem /var/fd70b569/9999.log 52463 lorem\n Lorem 973113 lorem 123e4567-e89b-12d3-a456-426655440000 lorem 8888 lorem\n  https://crates.io/23456/fd70b569 lorem");
-    let custom = ["CUSTOM-[0-9]{4,}", "ISSUE-[0-9]{3}"].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let options = Options {
+      regexp: [("custom", "CUSTOM-[0-9]{4,}"), ("issue", "ISSUE-[0-9]{3}")].to_vec(),
+      ..Options::default()
+    };
+    let results = State::new(&lines, "abcd", options).matches(false, false);
 
     assert_eq!(results.len(), 9);
     assert_eq!(results.get(0).unwrap().text.clone(), "http://foo.bar");
     assert_eq!(results.get(1).unwrap().text.clone(), "CUSTOM-52463");
     assert_eq!(results.get(2).unwrap().text.clone(), "ISSUE-123");
+    assert_eq!(results.get(1).unwrap().pattern, "custom");
+    assert_eq!(results.get(2).unwrap().pattern, "issue");
     assert_eq!(
       results.get(3).unwrap().text.clone(),
       "/var/fd70b569/9999.log"
@@ -1,54 +1,268 @@
 use super::*;
-use rustbox::Key;
-use rustbox::{Color, OutputMode, RustBox};
+use adjust::Span;
+use granularities::Granularity;
+use keymaps::{Action, Keymap, BACK_TAB};
+use modifiers::Modifier;
+use operators::Operator;
+use rustbox::{Color, InitOptions, InputMode, OutputMode, RustBox};
+use rustbox::{Key, Mouse};
 use std::char;
+use std::collections::{HashMap, VecDeque};
 use std::default::Default;
+use std::time::Duration;
+
+// Texts picked in the overlay and what to do with them, or the copy-mode
+// movement requested instead. The pattern is only known when a single match is
+// picked, and the position is the one of the first match.
+pub struct Pick {
+  pub texts: Vec<String>,
+  pub pattern: Option<String>,
+  pub position: Option<(usize, usize)>,
+  pub operator: Operator,
+  pub movement: Option<String>,
+}
+
+// Queries typed in the overlay to change the hinted matches
+#[derive(Clone, Copy, PartialEq)]
+enum Prompt {
+  Filter,
+  Search,
+}
+
+// Keys typed in the overlay, or matches clicked with the operator of the button
+enum Input {
+  Key(Key),
+  Click(usize, Operator),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+  Up,
+  Down,
+  Left,
+  Right,
+}
+
+// termbox doesn't know Shift-Tab, which arrives as Esc followed by `[Z`. Any
+// other event read after an Esc is queued to be handled next.
+fn back_tab(rustbox: &RustBox, queued: &mut VecDeque<rustbox::Event>) -> bool {
+  let mut peeked = Vec::new();
+  let mut matched = 0;
+
+  for &expected in ['[', 'Z'].iter() {
+    let event = match queued.pop_front() {
+      Some(event) => event,
+      None => match rustbox.peek_event(Duration::from_millis(0), false) {
+        Ok(rustbox::Event::NoEvent) | Err(_) => break,
+        Ok(event) => event,
+      },
+    };
+
+    peeked.push(event);
+
+    match event {
+      rustbox::Event::KeyEvent(Key::Char(ch)) if ch == expected => matched += 1,
+      _ => break,
+    }
+  }
+
+  if matched < 2 {
+    for event in peeked.into_iter().rev() {
+      queued.push_front(event);
+    }
+  }
+
+  matched == 2
+}
+
+// Closest match to another one in a direction of the screen, looking first at
+// the closest rows and then at the closest columns
+fn nearest(lines: &[&str], matches: &[state::Match], from: usize, direction: Direction) -> Option<usize> {
+  let column = |mat: &state::Match| lines[mat.y as usize][0..mat.x as usize].chars().count() as i32;
+  let origin = matches.get(from)?;
+  let (x, y) = (column(origin), origin.y);
+
+  matches
+    .iter()
+    .enumerate()
+    .filter(|&(_, mat)| match direction {
+      Direction::Up => mat.y < y,
+      Direction::Down => mat.y > y,
+      Direction::Left => mat.y == y && column(mat) < x,
+      Direction::Right => mat.y == y && column(mat) > x,
+    })
+    .min_by_key(|&(_, mat)| ((mat.y - y).abs(), (column(mat) - x).abs()))
+    .map(|(index, _)| index)
+}
+
+// First match at least `rows` rows away from another one
+fn page(matches: &[state::Match], from: usize, rows: i32) -> usize {
+  let y = matches.get(from).map(|mat| mat.y).unwrap_or(0);
+
+  if rows > 0 {
+    matches
+      .iter()
+      .position(|mat| mat.y >= y + rows)
+      .unwrap_or(matches.len().saturating_sub(1))
+  } else {
+    matches.iter().rposition(|mat| mat.y <= y + rows).unwrap_or(0)
+  }
+}
+
+// Match drawn under a cell of the screen, either over its text or its hint in
+// the gutter
+fn clicked(lines: &[&str], matches: &[state::Match], gutter: usize, x: i32, y: i32) -> Option<usize> {
+  let x = x as usize;
+
+  matches.iter().position(|mat| {
+    let start = gutter + lines[mat.y as usize][0..mat.x as usize].chars().count();
+    let end = start + mat.text.chars().count();
+
+    mat.y == y && ((start <= x && x < end) || x < gutter)
+  })
+}
+
+// Operator of the matches picked at once, the last one chosen with anything
+// but copy
+fn multi_operator(chosen: &[(state::Match, Operator)]) -> Operator {
+  chosen
+    .iter()
+    .rev()
+    .map(|&(_, operator)| operator)
+    .find(|&operator| operator != Operator::Copy)
+    .unwrap_or(Operator::Copy)
+}
+
+// Texts picked with two hints instead of one
+#[derive(Clone, Copy, PartialEq)]
+enum Selection {
+  Range,
+  Block,
+}
+
+// Match whose text is being grown or shrunk, with the cells of its line and the
+// operator it was picked with
+struct Adjusting<'a> {
+  mat: state::Match<'a>,
+  cells: Vec<(usize, usize, char)>,
+  span: Span,
+  operator: Operator,
+}
+
+// Texts, operator and matches of a pick, with their pattern when a single match
+// is picked
+type Picked<'a> = (Vec<String>, Operator, Vec<state::Match<'a>>, Option<&'a str>);
+
+// Settings of the overlay
+pub struct Options<'a> {
+  pub reverse: bool,
+  pub unique: bool,
+  pub contrast: bool,
+  pub position: &'a str,
+  pub select_foreground_color: Color,
+  pub foreground_color: Color,
+  pub background_color: Color,
+  pub hint_background_color: Color,
+  pub hint_foreground_color: Color,
+  pub copy_mode_cursor_up_key: Option<char>,
+  pub copy_mode_cursor_down_key: Option<char>,
+  pub upcase_modifier: Modifier,
+  pub keymap: Keymap,
+  pub multi: bool,
+  pub sticky: bool,
+  pub adjust: bool,
+  pub granularity: Granularity,
+  pub status: bool,
+  pub command: &'a str,
+  pub upcase_command: &'a str,
+  pub pattern_commands: Vec<(&'a str, &'a str)>,
+  pub pattern_upcase_commands: Vec<(&'a str, &'a str)>,
+  pub open_command: &'a str,
+  pub edit_command: &'a str,
+  pub send_command: &'a str,
+}
+
+// Hints on the left, in the colors of the terminal
+impl<'a> Default for Options<'a> {
+  fn default() -> Options<'a> {
+    Options {
+      reverse: false,
+      unique: false,
+      contrast: false,
+      position: "left",
+      select_foreground_color: Color::Default,
+      foreground_color: Color::Default,
+      background_color: Color::Default,
+      hint_background_color: Color::Default,
+      hint_foreground_color: Color::Default,
+      copy_mode_cursor_up_key: None,
+      copy_mode_cursor_down_key: None,
+      upcase_modifier: Modifier::default(),
+      keymap: Keymap::default(),
+      multi: false,
+      sticky: false,
+      adjust: false,
+      granularity: Granularity::default(),
+      status: false,
+      command: "",
+      upcase_command: "",
+      pattern_commands: Vec::new(),
+      pattern_upcase_commands: Vec::new(),
+      open_command: "",
+      edit_command: "",
+      send_command: "",
+    }
+  }
+}
 
 pub struct View<'a> {
   state: &'a mut state::State<'a>,
   skip: usize,
-  reverse: bool,
-  unique: bool,
-  contrast: bool,
-  position: &'a str,
-  select_foreground_color: Color,
-  foreground_color: Color,
-  background_color: Color,
-  hint_background_color: Color,
-  hint_foreground_color: Color,
-  copy_mode_cursor_up_key: Option<char>,
-  copy_mode_cursor_down_key: Option<char>,
+  options: Options<'a>,
+}
+
+// Patterns of the matches, in order of appearance
+fn categories<'a>(matches: &[state::Match<'a>]) -> Vec<&'a str> {
+  let mut categories = Vec::new();
+
+  for mat in matches {
+    if !categories.contains(&mat.pattern) {
+      categories.push(mat.pattern);
+    }
+  }
+
+  categories
+}
+
+// Texts of all the matches of a pattern without duplicates, in screen order
+fn texts_of(state: &state::State, matches: &[state::Match], pattern: &str) -> Vec<String> {
+  let mut texts: Vec<String> = Vec::new();
+
+  for mat in matches.iter().filter(|mat| mat.pattern == pattern) {
+    let text = state.text(mat);
+
+    if !texts.contains(&text) {
+      texts.push(text);
+    }
+  }
+
+  texts
 }
 
 impl<'a> View<'a> {
-  pub fn new(
-    state: &'a mut state::State<'a>,
-    reverse: bool,
-    unique: bool,
-    contrast: bool,
-    position: &'a str,
-    select_foreground_color: Color,
-    foreground_color: Color,
-    background_color: Color,
-    hint_foreground_color: Color,
-    hint_background_color: Color,
-    copy_mode_cursor_up_key: Option<char>,
-    copy_mode_cursor_down_key: Option<char>,
-  ) -> View<'a> {
+  pub fn new(state: &'a mut state::State<'a>, options: Options<'a>) -> View<'a> {
     View {
       state: state,
       skip: 0,
-      reverse: reverse,
-      unique: unique,
-      contrast: contrast,
-      position: position,
-      select_foreground_color: select_foreground_color,
-      foreground_color: foreground_color,
-      background_color: background_color,
-      hint_foreground_color: hint_foreground_color,
-      hint_background_color: hint_background_color,
-      copy_mode_cursor_up_key: copy_mode_cursor_up_key,
-      copy_mode_cursor_down_key: copy_mode_cursor_down_key,
+      options,
+    }
+  }
+
+  fn first(&self, count: usize) -> usize {
+    if self.options.reverse {
+      count.saturating_sub(1)
+    } else {
+      0
     }
   }
 
@@ -64,8 +278,17 @@ impl<'a> View<'a> {
     }
   }
 
+  fn go(&mut self, matches: &[state::Match], direction: Direction) {
+    match nearest(self.state.lines, matches, self.skip, direction) {
+      Some(index) => self.skip = index,
+      None if direction == Direction::Left => self.prev(),
+      None if direction == Direction::Right => self.next(matches.len().saturating_sub(1)),
+      None => {}
+    }
+  }
+
   fn make_hint_text(&self, hint: &str) -> String {
-    let text = if self.contrast {
+    let text = if self.options.contrast {
       format!("[{}]", hint).to_string()
     } else {
       hint.to_string()
@@ -74,27 +297,207 @@ impl<'a> View<'a> {
     text
   }
 
-  pub fn present(&mut self) -> Option<(String, bool, Option<String>)> {
-    let mut rustbox = match RustBox::init(Default::default()) {
+  // Panel with the active key bindings and what picking a hint does
+  fn help_lines(&self) -> Vec<(String, Color)> {
+    let heading = self.options.hint_foreground_color;
+    let mut lines = vec![
+      ("Press any key to close this help".to_string(), self.options.select_foreground_color),
+      ("Keys".to_string(), heading),
+    ];
+
+    let copy_mode_keys = [
+      ("copy-up", self.options.copy_mode_cursor_up_key),
+      ("copy-down", self.options.copy_mode_cursor_down_key),
+    ];
+
+    let bindings = self.options.keymap.bindings().into_iter().chain(
+      copy_mode_keys
+        .iter()
+        .filter_map(|&(name, key)| key.map(|key| (name, key.to_string()))),
+    );
+
+    for (name, keys) in bindings.filter(|binding| !binding.1.is_empty()) {
+      lines.push((format!("  {:<10} {}", name, keys), self.options.hint_foreground_color));
+    }
+
+    lines.push(("Patterns".to_string(), heading));
+
+    // Every pattern is drawn with the same color, named too for the monochrome
+    // terminals
+    for pattern in self.state.patterns() {
+      let color = colors::get_color_name(self.options.foreground_color);
+
+      lines.push((format!("  {:<10} {}", pattern, color), self.options.foreground_color));
+    }
+
+    lines.push(("Alphabet".to_string(), heading));
+    lines.push((format!("  {}", self.state.alphabet()), self.options.hint_foreground_color));
+    // The last command given for a pattern wins, like when picking
+    let command_for = |commands: &[(&'a str, &'a str)], pattern: &str, default: &'a str| {
+      commands
+        .iter()
+        .rev()
+        .find(|&&(name, _)| name == pattern)
+        .map(|&(_, command)| command)
+        .unwrap_or(default)
+    };
+
+    let mut overridden: Vec<&str> = Vec::new();
+
+    let named = self.options.pattern_commands.iter().chain(&self.options.pattern_upcase_commands);
+
+    for &(pattern, _) in named {
+      if !overridden.contains(&pattern) {
+        overridden.push(pattern);
+      }
+    }
+
+    lines.push(("Pick".to_string(), heading));
+    lines.push((format!("  {}", self.options.command), self.options.hint_foreground_color));
+
+    for &pattern in overridden.iter() {
+      let command = command_for(&self.options.pattern_commands, pattern, self.options.command);
+
+      lines.push((format!("  {:<10} {}", pattern, command), self.options.hint_foreground_color));
+    }
+
+    lines.push((format!("Upcase: {}", self.options.upcase_modifier.description()), heading));
+    lines.push((
+      format!("  {} and then {}", self.options.command, self.options.upcase_command),
+      self.options.hint_foreground_color,
+    ));
+
+    for &pattern in overridden.iter() {
+      let command = command_for(&self.options.pattern_commands, pattern, self.options.command);
+      let upcase_command =
+        command_for(&self.options.pattern_upcase_commands, pattern, self.options.upcase_command);
+
+      lines.push((
+        format!("  {:<10} {} and then {}", pattern, command, upcase_command),
+        self.options.hint_foreground_color,
+      ));
+    }
+
+    let operators = [
+      ("open", self.options.open_command),
+      ("edit", self.options.edit_command),
+      ("send", self.options.send_command),
+    ];
+
+    lines.push(("Operators".to_string(), heading));
+
+    for &(name, command) in operators.iter() {
+      lines.push((format!("  {:<10} {}", name, command), self.options.hint_foreground_color));
+    }
+
+    lines
+  }
+
+  // Mode, typed hint, amount of matches and the pattern of the selected match,
+  // with its whole text when it doesn't fit in the pane
+  fn status_line(
+    &self,
+    mode: &str,
+    typed_hint: &str,
+    matches: &[state::Match],
+    gutter: usize,
+    width: usize,
+  ) -> String {
+    let mut text = format!("[{}]", mode);
+
+    if !typed_hint.is_empty() {
+      text.push_str(&format!(" {}", typed_hint));
+    }
+
+    text.push_str(&format!(" {} matches", matches.len()));
+
+    if let Some(mat) = matches.get(self.skip) {
+      let line = &self.state.lines[mat.y as usize];
+      let end = gutter + line[0..mat.x as usize].chars().count() + mat.text.chars().count();
+
+      text.push_str(&format!(" {}", mat.pattern));
+
+      if end > width {
+        text.push_str(&format!(": {}", self.state.text(mat)));
+      }
+    }
+
+    text
+  }
+
+  // In sticky mode every pick goes to `on_pick` and the overlay stays open
+  pub fn present<F>(&mut self, mut on_pick: F) -> Option<Pick>
+  where
+    F: FnMut(&Pick),
+  {
+    let mut rustbox = match RustBox::init(InitOptions {
+      input_mode: InputMode::EscMouse,
+      ..Default::default()
+    }) {
       Result::Ok(v) => v,
       Result::Err(e) => panic!("{}", e),
     };
 
     rustbox.set_output_mode(OutputMode::EightBit);
 
+    // The status line takes the last row, so nothing is hinted under it
+    if self.options.status {
+      self.state.hide_from(rustbox.height().saturating_sub(1));
+    }
+
     let mut typed_hint: String = "".to_owned();
-    let matches = self.state.matches(self.reverse, self.unique);
-    let longest_hint = matches
-      .iter()
-      .filter_map(|m| m.hint.clone())
-      .max_by(|x, y| x.len().cmp(&y.len()))
-      .unwrap()
-      .clone();
+    let mut missed: Option<char> = None;
+    let mut help = false;
+    let mut upcase = false;
+    let mut operator: Option<Operator> = None;
+    let mut confirming: Option<usize> = None;
+    let mut prompt: Option<(Prompt, String)> = None;
+    let mut chosen: Vec<(state::Match, Operator)> = Vec::new();
+    let mut picked: Vec<state::Match> = Vec::new();
+    let mut multi = self.options.multi;
+    let mut bulk: Option<usize> = None;
+    let mut selection: Option<(Selection, Option<state::Match>)> = None;
+    let mut adjusting: Option<Adjusting> = None;
+    let mut editing = false;
+    let mut queued: VecDeque<rustbox::Event> = VecDeque::new();
+    let all_matches = self.state.units(self.options.granularity, self.options.reverse, self.options.unique);
+    let mut matches = all_matches.clone();
     let mut selected;
+    let widest = self
+      .state
+      .lines
+      .iter()
+      .map(|line| line.trim_end().chars().count())
+      .max()
+      .unwrap_or(0);
 
-    self.skip = if self.reverse { matches.len() - 1 } else { 0 };
+    self.skip = self.first(matches.len());
 
     loop {
+      let longest_hint = matches
+        .iter()
+        .filter_map(|m| m.hint.as_ref())
+        .map(|hint| hint.chars().count())
+        .max()
+        .unwrap_or(0);
+
+      let mut duplicates: HashMap<&str, usize> = HashMap::new();
+
+      if self.options.unique {
+        for hint in matches.iter().filter_map(|m| m.hint.as_ref()) {
+          *duplicates.entry(hint.as_str()).or_insert(0) += 1;
+        }
+      }
+
+      // Lines make room for the hints on their left, unless some of them
+      // would be cut at the right edge
+      let hint_width = self.make_hint_text(&"x".repeat(longest_hint)).chars().count() + 1;
+      let gutter = if self.options.granularity.uses_gutter() && widest + hint_width <= rustbox.width() {
+        hint_width
+      } else {
+        0
+      };
+
       rustbox.clear();
       rustbox.present();
 
@@ -105,7 +508,7 @@ impl<'a> View<'a> {
           let text = self.make_hint_text(line);
 
           rustbox.print(
-            0,
+            gutter,
             index,
             rustbox::RB_NORMAL,
             Color::White,
@@ -117,106 +520,647 @@ impl<'a> View<'a> {
 
       selected = matches.get(self.skip);
 
+      let categories = categories(&matches);
+      let category = bulk.and_then(|index| categories.get(index));
+
       for mat in matches.iter() {
-        let selected_color = if selected == Some(mat) {
-          self.select_foreground_color
+        let highlighted = match category {
+          Some(pattern) => *pattern == mat.pattern,
+          None => selected == Some(mat),
+        };
+
+        let selected_color = if highlighted {
+          self.options.select_foreground_color
         } else {
-          self.foreground_color
+          self.options.foreground_color
         };
 
         // Find long utf sequences and extract it from mat.x
         let line = &self.state.lines[mat.y as usize];
         let prefix = &line[0..mat.x as usize];
         let extra = prefix.len() - prefix.chars().count();
-        let offset = (mat.x as usize) - extra;
+        let offset = gutter + (mat.x as usize) - extra;
         let text = self.make_hint_text(mat.text);
+        let anchored = match selection {
+          Some((_, Some(ref anchor))) => anchor == mat,
+          _ => false,
+        };
+
+        let style = if chosen.iter().any(|(chosen, _)| chosen == mat) || anchored {
+          rustbox::RB_REVERSE
+        } else if picked.contains(mat) {
+          rustbox::RB_UNDERLINE
+        } else {
+          rustbox::RB_NORMAL
+        };
 
         rustbox.print(
           offset,
           mat.y as usize,
-          rustbox::RB_NORMAL,
+          style,
           selected_color,
-          self.background_color,
+          self.options.background_color,
           &text,
         );
 
-        if let Some(ref hint) = mat.hint {
-          let extra_position = if self.position == "left" {
+        if let Some(hint) = mat.hint.as_ref().filter(|hint| hint.starts_with(&typed_hint)) {
+          let extra_position = if self.options.position == "left" {
             0
           } else {
-            text.len() - mat.hint.clone().unwrap().len()
+            text.chars().count().saturating_sub(hint.chars().count())
+          };
+
+          let position = if self.options.granularity.uses_gutter() {
+            0
+          } else {
+            offset + extra_position
           };
 
           let text = self.make_hint_text(hint.as_str());
 
           rustbox.print(
-            offset + extra_position,
+            position,
             mat.y as usize,
             rustbox::RB_BOLD,
-            self.hint_foreground_color,
-            self.hint_background_color,
+            self.options.hint_foreground_color,
+            self.options.hint_background_color,
+            &text,
+          );
+
+          if !typed_hint.is_empty() {
+            let contrast = if self.options.contrast { 1 } else { 0 };
+
+            rustbox.print(
+              position + contrast,
+              mat.y as usize,
+              rustbox::RB_BOLD | rustbox::RB_UNDERLINE,
+              self.options.select_foreground_color,
+              self.options.hint_background_color,
+              &typed_hint,
+            );
+          }
+
+          if let Some(count) = duplicates.get(hint.as_str()).filter(|&&count| count > 1) {
+            rustbox.print(
+              position + text.chars().count(),
+              mat.y as usize,
+              rustbox::RB_NORMAL,
+              self.options.hint_foreground_color,
+              self.options.hint_background_color,
+              &format!("×{}", count),
+            );
+          }
+        }
+      }
+
+      if let Some(Adjusting { ref mat, ref cells, span, .. }) = adjusting {
+        let line = &self.state.lines[mat.y as usize];
+        let offset = gutter + line[0..cells[span.start].0].chars().count();
+        let text = cells[span.start..span.end]
+          .iter()
+          .map(|cell| cell.2)
+          .collect::<String>();
+
+        rustbox.print(
+          offset,
+          mat.y as usize,
+          rustbox::RB_REVERSE,
+          self.options.select_foreground_color,
+          self.options.background_color,
+          &text,
+        );
+      }
+
+      let bottom = if self.options.status {
+        rustbox.height().saturating_sub(2)
+      } else {
+        rustbox.height() - 1
+      };
+
+      if let Some(index) = bulk {
+        let mut position = 0;
+
+        for (number, name) in categories.iter().enumerate() {
+          let text = format!("{}:{} ", number + 1, name);
+          let color = if number == index {
+            self.options.select_foreground_color
+          } else {
+            self.options.hint_foreground_color
+          };
+
+          rustbox.print(
+            position,
+            bottom,
+            rustbox::RB_BOLD,
+            color,
+            self.options.hint_background_color,
             &text,
           );
+
+          position += text.chars().count();
+        }
+      } else if let Some((kind, ref query)) = prompt {
+        let symbol = if kind == Prompt::Filter { '/' } else { ':' };
+
+        rustbox.print(
+          0,
+          bottom,
+          rustbox::RB_BOLD,
+          self.options.hint_foreground_color,
+          self.options.hint_background_color,
+          &format!("{}{}", symbol, query),
+        );
+      } else if let Some(letter) = missed {
+        rustbox.print(
+          0,
+          bottom,
+          rustbox::RB_BOLD,
+          Color::Red,
+          self.options.hint_background_color,
+          &format!("No hint starts with {}{}", typed_hint, letter),
+        );
+      }
+
+      if self.options.status {
+        let mode = if help {
+          "help".to_string()
+        } else if adjusting.is_some() {
+          "adjust".to_string()
+        } else if bulk.is_some() {
+          "bulk".to_string()
+        } else if let Some((Selection::Range, _)) = selection {
+          "range".to_string()
+        } else if let Some((Selection::Block, _)) = selection {
+          "block".to_string()
+        } else if let Some(&(kind, _)) = prompt.as_ref().filter(|_| editing) {
+          if kind == Prompt::Filter { "filter" } else { "search" }.to_string()
+        } else if multi {
+          format!("multi-select ({})", chosen.len())
+        } else {
+          "hint".to_string()
+        };
+
+        let width = rustbox.width();
+        let text = self.status_line(&mode, &typed_hint, &matches, gutter, width);
+
+        rustbox.print(
+          0,
+          rustbox.height() - 1,
+          rustbox::RB_BOLD,
+          self.options.hint_foreground_color,
+          self.options.hint_background_color,
+          &format!("{:<width$}", text, width = width),
+        );
+      }
+
+      if help {
+        let lines = self.help_lines();
+        let width = lines.iter().map(|line| line.0.chars().count()).max().unwrap_or(0);
+
+        for (index, &(ref text, color)) in lines.iter().enumerate() {
+          rustbox.print(
+            0,
+            index,
+            rustbox::RB_BOLD,
+            color,
+            self.options.hint_background_color,
+            &format!(" {:<width$} ", text, width = width),
+          );
         }
       }
 
       rustbox.present();
 
-      match rustbox.poll_event(false) {
-        Ok(rustbox::Event::KeyEvent(key)) => match key {
-          Key::Esc => {
-            break;
-          }
-          Key::Enter => match matches.iter().enumerate().find(|&h| h.0 == self.skip) {
-            Some(hm) => return Some((hm.1.text.to_string(), false, None)),
-            _ => panic!("Match not found?"),
-          },
-          Key::Up => {
-            self.prev();
-          }
-          Key::Down => {
-            self.next(matches.len() - 1);
-          }
-          Key::Left => {
-            self.prev();
+      let event = match queued.pop_front() {
+        Some(event) => Ok(event),
+        None => rustbox.poll_event(false),
+      };
+
+      let input = match event {
+        Ok(rustbox::Event::KeyEvent(key)) => {
+          if key == Key::Esc && back_tab(&rustbox, &mut queued) {
+            Input::Key(BACK_TAB)
+          } else {
+            Input::Key(key)
           }
-          Key::Right => {
-            self.next(matches.len() - 1);
+        }
+        Ok(rustbox::Event::MouseEvent(button, x, y)) => {
+          let clicked_operator = match button {
+            Mouse::Left => operator.unwrap_or(Operator::Copy),
+            Mouse::Right => Operator::Paste,
+            Mouse::WheelUp => {
+              self.prev();
+              continue;
+            }
+            Mouse::WheelDown => {
+              self.next(matches.len().saturating_sub(1));
+              continue;
+            }
+            _ => continue,
+          };
+
+          match clicked(self.state.lines, &matches, gutter, x, y) {
+            Some(index) => Input::Click(index, clicked_operator),
+            None => continue,
           }
-          Key::Char(ch) => {
-            let key = ch.to_string();
-            let lower_key = key.to_lowercase();
+        }
+        Err(e) => panic!("{}", e),
+        _ => continue,
+      };
+
+      if help {
+        help = false;
+        continue;
+      }
 
-            if let Some(up_key) = self.copy_mode_cursor_up_key {
-              if up_key == ch {
-                return Some((String::new(), false, Some(String::from("cursor-up"))));
+      missed = None;
+
+      let pending = operator.unwrap_or(Operator::Copy);
+      let mut pick: Option<(usize, Operator)> = None;
+      let mut result: Option<Picked> = None;
+
+      match input {
+        Input::Click(index, clicked_operator) => {
+          confirming = None;
+          adjusting = None;
+          bulk = None;
+          editing = false;
+          pick = Some((index, clicked_operator));
+        }
+        Input::Key(key) => {
+          let action = self.options.keymap.action(key);
+
+          if let Some(index) = confirming.take() {
+            // Cancel only drops the hint waiting for confirmation
+            match action {
+              Some(Action::Cancel) => typed_hint.clear(),
+              Some(Action::Confirm) => pick = Some((index, Operator::Paste)),
+              _ => pick = Some((index, pending)),
+            }
+          } else if let Some(adjusted) = adjusting.take() {
+            let Adjusting { ref mat, ref cells, span, operator: chosen_with } = adjusted;
+
+            match (action, key) {
+              (Some(Action::Cancel), _) => {}
+              (Some(Action::Confirm), _) => {
+                let text = cells[span.start..span.end].iter().map(|cell| cell.2).collect();
+
+                result = Some((vec![text], chosen_with, vec![mat.clone()], Some(mat.pattern)));
               }
+              (_, Key::Char(ch)) => {
+                let chars = cells.iter().map(|cell| cell.2).collect::<Vec<_>>();
+                let span = match adjust::get_adjustment(ch) {
+                  Some((grow, side, unit)) => span.adjust(&chars, grow, side, unit),
+                  None => span,
+                };
+
+                adjusting = Some(Adjusting { span, ..adjusted });
+              }
+              _ => adjusting = Some(adjusted),
+            }
+          } else if let Some(index) = bulk {
+            let chosen_category = match (action, key) {
+              (Some(Action::Cancel), _) => {
+                bulk = None;
+                None
+              }
+              (Some(Action::Bulk), _) | (Some(Action::Next), _) => {
+                bulk = Some((index + 1) % categories.len());
+                None
+              }
+              (Some(Action::Confirm), _) => categories.get(index),
+              (_, Key::Char(ch)) => ch
+                .to_digit(10)
+                .and_then(|number| categories.get((number as usize).wrapping_sub(1))),
+              _ => None,
+            };
+
+            if let Some(category) = chosen_category {
+              let picked = matches
+                .iter()
+                .filter(|mat| mat.pattern == *category)
+                .cloned()
+                .collect();
+
+              result = Some((texts_of(self.state, &matches, category), pending, picked, None));
             }
+          } else if editing {
+            // Characters are always typed in the query, even if they are bound
+            let changed = match (action, key) {
+              (_, Key::Char(ch)) => {
+                if let Some((_, ref mut query)) = prompt {
+                  query.push(ch);
+                }
+
+                true
+              }
+              (_, Key::Backspace) => {
+                if let Some((_, ref mut query)) = prompt {
+                  query.pop();
+                }
 
-            if let Some(down_key) = self.copy_mode_cursor_down_key {
-              if down_key == ch {
-                return Some((String::new(), false, Some(String::from("cursor-down"))));
+                true
               }
+              (Some(Action::Cancel), _) => {
+                prompt = None;
+                editing = false;
+                true
+              }
+              (Some(Action::Confirm), _) => {
+                editing = false;
+
+                if matches.len() == 1 && !multi {
+                  pick = Some((0, pending));
+                }
+
+                false
+              }
+              _ => false,
+            };
+
+            if changed {
+              matches = match prompt {
+                Some((Prompt::Filter, ref query)) => {
+                  self.state.filter(&all_matches, query, self.options.reverse, self.options.unique)
+                }
+                Some((Prompt::Search, ref query)) if !query.is_empty() => {
+                  self.state.search(query, self.options.reverse, self.options.unique)
+                }
+                _ => all_matches.clone(),
+              };
+
+              typed_hint.clear();
+              self.skip = self.first(matches.len());
             }
+          } else {
+            let letter = match (action, key) {
+              (Some(Action::Cancel), _) if selection.is_some() => {
+                selection = None;
+                None
+              }
+              (Some(Action::Cancel), _) if !typed_hint.is_empty() || operator.is_some() || upcase => {
+                typed_hint.clear();
+                operator = None;
+                upcase = false;
+                None
+              }
+              (Some(Action::Cancel), _) => {
+                break;
+              }
+              (_, Key::Backspace) => {
+                typed_hint.pop();
+                upcase = false;
+                None
+              }
+              (Some(Action::Confirm), _) if selection.is_some() => {
+                pick = matches.get(self.skip).map(|_| (self.skip, pending));
+                None
+              }
+              (Some(Action::Confirm), _) if multi && !chosen.is_empty() => {
+                let texts = chosen.iter().map(|(mat, _)| self.state.text(mat)).collect();
+                let mats = chosen.iter().map(|(mat, _)| mat.clone()).collect();
+
+                result = Some((texts, multi_operator(&chosen), mats, None));
+                None
+              }
+              (Some(Action::Confirm), _) => {
+                if let Some(mat) = matches.get(self.skip) {
+                  let texts = vec![self.state.text(mat)];
+
+                  result = Some((texts, pending, vec![mat.clone()], Some(mat.pattern)));
+                }
+
+                None
+              }
+              (Some(Action::Up), _) => {
+                self.go(&matches, Direction::Up);
+                None
+              }
+              (Some(Action::Down), _) => {
+                self.go(&matches, Direction::Down);
+                None
+              }
+              (Some(Action::Left), _) => {
+                self.go(&matches, Direction::Left);
+                None
+              }
+              (Some(Action::Right), _) => {
+                self.go(&matches, Direction::Right);
+                None
+              }
+              (Some(Action::Next), _) if !matches.is_empty() => {
+                self.skip = (self.skip + 1) % matches.len();
+                None
+              }
+              (Some(Action::Prev), _) if !matches.is_empty() => {
+                self.skip = (self.skip + matches.len() - 1) % matches.len();
+                None
+              }
+              (Some(Action::First), _) => {
+                self.skip = 0;
+                None
+              }
+              (Some(Action::Last), _) => {
+                self.skip = matches.len().saturating_sub(1);
+                None
+              }
+              (Some(Action::PageUp), _) => {
+                self.skip = page(&matches, self.skip, -(rustbox.height() as i32 / 2));
+                None
+              }
+              (Some(Action::PageDown), _) => {
+                self.skip = page(&matches, self.skip, rustbox.height() as i32 / 2);
+                None
+              }
+              (_, Key::Ctrl(ch)) if self.options.upcase_modifier == Modifier::Ctrl => {
+                upcase = true;
+                Some(ch)
+              }
+              (Some(Action::Multi), _) if typed_hint.is_empty() => {
+                if multi {
+                  pick = matches.get(self.skip).map(|_| (self.skip, pending));
+                }
 
-            typed_hint.push_str(lower_key.as_str());
+                multi = true;
+                None
+              }
+              (Some(Action::Bulk), _) if typed_hint.is_empty() && !matches.is_empty() => {
+                bulk = Some(0);
+                None
+              }
+              (Some(Action::Range), _) if typed_hint.is_empty() && !matches.is_empty() => {
+                selection = Some((Selection::Range, None));
+                None
+              }
+              (Some(Action::Block), _) if typed_hint.is_empty() && !matches.is_empty() => {
+                selection = Some((Selection::Block, None));
+                None
+              }
+              (Some(Action::Operate(chosen)), _) if typed_hint.is_empty() => {
+                operator = Some(chosen);
+                None
+              }
+              (Some(Action::Help), _) if typed_hint.is_empty() => {
+                help = true;
+                None
+              }
+              (Some(Action::Filter), _) if typed_hint.is_empty() => {
+                if prompt.as_ref().map(|&(kind, _)| kind) != Some(Prompt::Filter) {
+                  prompt = Some((Prompt::Filter, String::new()));
+                }
 
-            match matches
-              .iter()
-              .find(|mat| mat.hint == Some(typed_hint.clone()))
-            {
-              Some(mat) => return Some((mat.text.to_string(), key != lower_key, None)),
-              None => {
-                if typed_hint.len() >= longest_hint.len() {
-                  break;
+                editing = true;
+                None
+              }
+              (Some(Action::Search), _) if typed_hint.is_empty() => {
+                if prompt.as_ref().map(|&(kind, _)| kind) != Some(Prompt::Search) {
+                  prompt = Some((Prompt::Search, String::new()));
+                }
+
+                editing = true;
+                None
+              }
+              (_, Key::Char(ch)) => {
+                if let Some(up_key) = self.options.copy_mode_cursor_up_key {
+                  if up_key == ch {
+                    return Some(Pick {
+                      texts: vec![],
+                      pattern: None,
+                      position: None,
+                      operator: Operator::Copy,
+                      movement: Some(String::from("cursor-up")),
+                    });
+                  }
+                }
+
+                if let Some(down_key) = self.options.copy_mode_cursor_down_key {
+                  if down_key == ch {
+                    return Some(Pick {
+                      texts: vec![],
+                      pattern: None,
+                      position: None,
+                      operator: Operator::Copy,
+                      movement: Some(String::from("cursor-down")),
+                    });
+                  }
+                }
+
+                let (letter, upcased) = self.options.upcase_modifier.letter(ch, &typed_hint, upcase);
+
+                upcase = upcased;
+                letter
+              }
+              _ => None,
+            };
+
+            if let Some(letter) = letter {
+              typed_hint.push(letter);
+
+              match matches
+                .iter()
+                .position(|mat| mat.hint == Some(typed_hint.clone()))
+              {
+                Some(index) => {
+                  if self.options.upcase_modifier == Modifier::Enter {
+                    self.skip = index;
+                    confirming = Some(index);
+                  } else {
+                    pick = Some((index, if upcase { Operator::Paste } else { pending }));
+                  }
+                }
+                None => {
+                  let candidates = matches
+                    .iter()
+                    .filter_map(|mat| mat.hint.as_ref())
+                    .any(|hint| hint.starts_with(&typed_hint));
+
+                  if !candidates {
+                    typed_hint.pop();
+                    missed = Some(letter);
+                  }
                 }
               }
             }
           }
-          _ => {}
-        },
-        Err(e) => panic!("{}", e),
-        _ => {}
+        }
+      }
+
+      if let Some((index, chosen_with)) = pick {
+        operator = None;
+        upcase = false;
+
+        if let Some((kind, anchor)) = selection.take() {
+          let mat = matches[index].clone();
+
+          match anchor {
+            None => selection = Some((kind, Some(mat))),
+            Some(anchor) => {
+              let text = match kind {
+                Selection::Range => self.state.range(&anchor, &mat),
+                Selection::Block => self.state.block(&anchor, &mat),
+              };
+
+              result = Some((vec![text], chosen_with, vec![anchor, mat], None));
+            }
+          }
+
+          typed_hint.clear();
+        } else if multi {
+          match chosen.iter().position(|(mat, _)| *mat == matches[index]) {
+            Some(position) => {
+              chosen.remove(position);
+            }
+            None => chosen.push((matches[index].clone(), chosen_with)),
+          }
+
+          typed_hint.clear();
+        } else if self.options.adjust {
+          let mat = matches[index].clone();
+          let cells = self.state.cells(mat.y as usize);
+          let start = mat.x as usize;
+          let end = start + mat.text.len();
+          let span = Span {
+            start: cells.iter().position(|cell| cell.0 >= start).unwrap_or(0),
+            end: cells.iter().position(|cell| cell.0 >= end).unwrap_or(cells.len()),
+          };
+
+          adjusting = Some(Adjusting {
+            mat,
+            cells,
+            span,
+            operator: chosen_with,
+          });
+          typed_hint.clear();
+        } else {
+          let mat = matches[index].clone();
+
+          let texts = vec![self.state.text(&mat)];
+
+          result = Some((texts, chosen_with, vec![mat.clone()], Some(mat.pattern)));
+        }
+      }
+
+      if let Some((texts, chosen_with, mats, pattern)) = result {
+        let result = Pick {
+          texts,
+          pattern: pattern.map(|pattern| pattern.to_string()),
+          position: mats.first().map(|mat| self.state.position(mat)),
+          operator: chosen_with,
+          movement: None,
+        };
+
+        if !self.options.sticky {
+          return Some(result);
+        }
+
+        on_pick(&result);
+
+        picked.extend(mats);
+        chosen.clear();
+        operator = None;
+        upcase = false;
+        typed_hint.clear();
+        bulk = None;
       }
     }
 
@@ -232,29 +1176,145 @@ mod tests {
     output.split("\n").collect::<Vec<&str>>()
   }
 
+  fn state<'a>(lines: &'a Vec<&'a str>) -> state::State<'a> {
+    state::State::new(lines, "abcd", state::Options::default())
+  }
+
+  fn view<'a>(state: &'a mut state::State<'a>) -> View<'a> {
+    let options = Options {
+      command: "tmux set-buffer {}",
+      upcase_command: "tmux paste-buffer",
+      ..Options::default()
+    };
+
+    View::new(state, options)
+  }
+
   #[test]
-  fn hint_text() {
+  fn bulk_texts() {
+    let lines = split("lorem /tmp/foo 127.0.0.1 /tmp/bar lorem /tmp/foo 127.0.0.2");
+    let state = state(&lines);
+    let matches = state.matches(true, false);
+
+    assert_eq!(categories(&matches), ["path", "ip"]);
+    assert_eq!(texts_of(&state, &matches, "path"), ["/tmp/foo", "/tmp/bar"]);
+    assert_eq!(texts_of(&state, &matches, "ip"), ["127.0.0.1", "127.0.0.2"]);
+  }
+
+  #[test]
+  fn spatial_navigation() {
+    let lines = split("/tmp/a   /tmp/b\n   /tmp/c\n/tmp/d        /tmp/e");
+    let state = state(&lines);
+    let matches = state.matches(false, false);
+
+    assert_eq!(nearest(&lines, &matches, 0, Direction::Down), Some(2));
+    assert_eq!(nearest(&lines, &matches, 1, Direction::Down), Some(2));
+    assert_eq!(nearest(&lines, &matches, 2, Direction::Down), Some(3));
+    assert_eq!(nearest(&lines, &matches, 4, Direction::Up), Some(2));
+    assert_eq!(nearest(&lines, &matches, 0, Direction::Up), None);
+    assert_eq!(nearest(&lines, &matches, 3, Direction::Right), Some(4));
+    assert_eq!(nearest(&lines, &matches, 2, Direction::Left), None);
+
+    assert_eq!(page(&matches, 0, 2), 3);
+    assert_eq!(page(&matches, 4, -1), 2);
+    assert_eq!(page(&matches, 4, -5), 0);
+  }
+
+  #[test]
+  fn clicked_matches() {
+    let lines = split("lorem /tmp/a ipsum\n/tmp/b");
+    let state = state(&lines);
+    let matches = state.matches(false, false);
+
+    assert_eq!(clicked(&lines, &matches, 0, 6, 0), Some(0));
+    assert_eq!(clicked(&lines, &matches, 0, 11, 0), Some(0));
+    assert_eq!(clicked(&lines, &matches, 0, 12, 0), None);
+    assert_eq!(clicked(&lines, &matches, 0, 2, 1), Some(1));
+    assert_eq!(clicked(&lines, &matches, 0, 8, 1), None);
+    assert_eq!(clicked(&lines, &matches, 3, 1, 1), Some(1));
+    assert_eq!(clicked(&lines, &matches, 3, 1, 2), None);
+  }
+
+  #[test]
+  fn multi_operators() {
+    let lines = split("/tmp/a /tmp/b /tmp/c");
+    let state = state(&lines);
+    let matches = state.matches(false, false);
+    let mut chosen = vec![(matches[0].clone(), Operator::Paste), (matches[1].clone(), Operator::Copy)];
+
+    assert_eq!(multi_operator(&chosen), Operator::Paste);
+
+    chosen.remove(0);
+
+    assert_eq!(multi_operator(&chosen), Operator::Copy);
+
+    chosen.push((matches[2].clone(), Operator::Open));
+
+    assert_eq!(multi_operator(&chosen), Operator::Open);
+  }
+
+  #[test]
+  fn help_text() {
     let lines = split("lorem 127.0.0.1 lorem");
-    let custom = [].to_vec();
-    let mut state = state::State::new(&lines, "abcd", &custom);
-    let mut view = View {
-      state: &mut state,
-      skip: 0,
-      reverse: false,
-      unique: false,
-      contrast: false,
-      position: &"",
-      select_foreground_color: rustbox::Color::Default,
-      foreground_color: rustbox::Color::Default,
-      background_color: rustbox::Color::Default,
-      hint_background_color: rustbox::Color::Default,
-      hint_foreground_color: rustbox::Color::Default,
+    let options = state::Options {
+      regexp: [("custom", "foo[0-9]+"), ("ticket", "T-[0-9]+")].to_vec(),
+      ..state::Options::default()
     };
+    let mut state = state::State::new(&lines, "asdf", options);
+    let mut view = view(&mut state);
+
+    view.options.copy_mode_cursor_up_key = Some('k');
+    view.options.upcase_modifier = Modifier::Leader(';');
+    view.options.keymap = keymaps::get_keymap(&["help=C-h"], None).unwrap();
+    view.options.pattern_commands = [("url", "xdg-open {}"), ("ticket", "open {}")].to_vec();
+    view.options.pattern_upcase_commands = [("url", "firefox {}"), ("path", "vim {}")].to_vec();
+    view.options.open_command = "xdg-open {}";
+
+    let help = view.help_lines().into_iter().map(|line| line.0).collect::<Vec<_>>();
+
+    assert!(help.contains(&"  cancel     esc".to_string()));
+    assert!(help.contains(&"  help       C-h".to_string()));
+    assert!(help.contains(&"  copy-up    k".to_string()));
+    assert!(help.contains(&"  custom foo[0-9]+ default".to_string()));
+    assert!(help.contains(&"  ticket     default".to_string()));
+    assert!(help.contains(&"  url        default".to_string()));
+    assert!(help.contains(&"  asdf".to_string()));
+    assert!(help.contains(&"Upcase: type ; before the hint".to_string()));
+    assert!(help.contains(&"  tmux set-buffer {} and then tmux paste-buffer".to_string()));
+    assert!(help.contains(&"  url        xdg-open {}".to_string()));
+    assert!(help.contains(&"  path       tmux set-buffer {}".to_string()));
+    assert!(help.contains(&"  url        xdg-open {} and then firefox {}".to_string()));
+    assert!(help.contains(&"  ticket     open {} and then tmux paste-buffer".to_string()));
+    assert!(help.contains(&"  path       tmux set-buffer {} and then vim {}".to_string()));
+    assert!(help.contains(&"  open       xdg-open {}".to_string()));
+  }
+
+  #[test]
+  fn status_text() {
+    let lines = split("lorem 127.0.0.1 lorem /var/log/syslog");
+    let mut state = state(&lines);
+    let matches = state.matches(false, false);
+    let mut view = view(&mut state);
+
+    assert_eq!(view.status_line("hint", "", &matches, 0, 80), "[hint] 2 matches ip");
+    assert_eq!(view.status_line("hint", "a", &matches, 0, 80), "[hint] a 2 matches ip");
+
+    view.skip = 1;
+
+    assert_eq!(view.status_line("hint", "", &matches, 0, 30), "[hint] 2 matches path: /var/log/syslog");
+    assert_eq!(view.status_line("hint", "", &[], 0, 30), "[hint] 0 matches");
+  }
+
+  #[test]
+  fn hint_text() {
+    let lines = split("lorem 127.0.0.1 lorem");
+    let mut state = state(&lines);
+    let mut view = view(&mut state);
 
     let result = view.make_hint_text("a");
     assert_eq!(result, "a".to_string());
 
-    view.contrast = true;
+    view.options.contrast = true;
     let result = view.make_hint_text("a");
     assert_eq!(result, "[a]".to_string());
   }
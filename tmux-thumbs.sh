@@ -18,15 +18,13 @@ function option {
   fi
 }
 
+# Values of repeated options go to their own array, so they can contain
+# spaces, like commands.
 function multi {
-  VALUES=""
-
   while read -r ITEM_KEY; do
     VALUE=$(tmux show -vg $ITEM_KEY 2> /dev/null)
-    VALUES="${VALUES} --$1 ${VALUE}"
-  done < <(tmux show -g 2> /dev/null | grep thumbs-$1- | cut -d' ' -f1)
-
-  echo ${VALUES}
+    MULTI_PARAMS+=("--$1=${VALUE}")
+  done < <(tmux show -g 2> /dev/null | grep -E "^@thumbs-$1-[0-9]+ " | cut -d' ' -f1)
 }
 
 PARAMS=()
@@ -41,11 +39,31 @@ PARAMS[7]=$(option hint-fg-color)
 PARAMS[8]=$(option select-fg-color)
 PARAMS[9]=$(option command)
 PARAMS[10]=$(option upcase-command)
-PARAMS[11]=$(multi regexp)
-PARAMS[12]=$(boolean contrast)
-PARAMS[13]=$(boolean osc52)
-PARAMS[14]=$(option copy-mode-up-key)
-PARAMS[15]=$(option copy-mode-down-key)
+PARAMS[11]=$(boolean contrast)
+PARAMS[12]=$(boolean osc52)
+PARAMS[13]=$(option copy-mode-up-key)
+PARAMS[14]=$(option copy-mode-down-key)
+PARAMS[15]=$(option alphabets-file)
+PARAMS[16]=$(option upcase-modifier)
+PARAMS[17]=$(option unique-normalize)
+PARAMS[18]=$(boolean multi)
+PARAMS[19]=$(option multi-separator)
+PARAMS[20]=$(boolean sticky)
+PARAMS[21]=$(boolean adjust)
+PARAMS[22]=$(option granularity)
+PARAMS[23]=$(option keymap-file)
+PARAMS[24]=$(boolean status)
+PARAMS[25]=$(option open-command)
+PARAMS[26]=$(option edit-command)
+PARAMS[27]=$(option send-command)
+
+MULTI_PARAMS=()
+multi regexp
+multi pattern-prefix
+multi keymap
+multi named-regexp
+multi pattern-command
+multi pattern-upcase-command
 
 # Remove empty arguments from PARAMS.
 # Otherwise, they would choke up tmux-thumbs when passed to it.
@@ -71,7 +89,7 @@ if [ ! -e "${BINARY}" ]; then
 fi
 
 CURRENT_PANE_ID=$(tmux list-panes -F "#{pane_id}:#{?pane_active,active,nope}" | grep active | cut -d: -f1)
-NEW_ID=$(tmux new-window -P -d -n "[thumbs]" "${BINARY}" "${PARAMS[@]}" "--tmux-pane=${CURRENT_PANE_ID}")
+NEW_ID=$(tmux new-window -P -d -n "[thumbs]" "${BINARY}" "${PARAMS[@]}" "${MULTI_PARAMS[@]}" "--tmux-pane=${CURRENT_PANE_ID}")
 NEW_PANE_ID=$(tmux list-panes -a | grep ${NEW_ID} | grep --color=never -o '%[0-9]\+')
 
 tmux swap-pane -d -s ${CURRENT_PANE_ID} -t ${NEW_PANE_ID}